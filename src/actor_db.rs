@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::ops::Not;
+use std::path::Path;
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    static ref ACTOR_DB: RwLock<ActorDb> = RwLock::new(ActorDb::default());
+}
+
+/// A performer with every name they are known by.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ActorEntry {
+    /// The canonical name used for folders and NFOs.
+    pub name: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// A photo URL or local file used instead of the scraped one.
+    #[serde(default)]
+    pub photo: String,
+}
+
+/// A local database mapping actor aliases to a canonical name and photo.
+///
+/// The database is a JSON file with an `actors` array of `ActorEntry`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ActorDb {
+    pub actors: Vec<ActorEntry>,
+    // lowercase name or alias -> index in actors
+    #[serde(skip)]
+    index: HashMap<String, usize>,
+}
+
+impl ActorDb {
+    pub fn load(file: &str) -> Result<ActorDb, Box<dyn Error>> {
+        if !Path::new(file).exists() {
+            return Ok(ActorDb::default());
+        }
+        let content = fs::read_to_string(file)?;
+        let mut db: ActorDb = serde_json::from_str(&content)
+            .map_err(|e| format!("[-]Fail to load actor database '{}': {}", file, e))?;
+        db.build_index();
+        Ok(db)
+    }
+
+    pub fn save(&self, file: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = Path::new(file).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(file, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Loads the database used while scraping.
+    pub fn init(file: &str) -> Result<(), Box<dyn Error>> {
+        let db = ActorDb::load(file)?;
+        println!("[+]Load {} actors from '{}'", db.actors.len(), file);
+        *ACTOR_DB.write().unwrap() = db;
+        Ok(())
+    }
+
+    pub fn get() -> std::sync::RwLockReadGuard<'static, ActorDb> {
+        ACTOR_DB.read().unwrap()
+    }
+
+    /// Merges the actors of another database file and returns how many entries were added.
+    ///
+    /// An imported entry sharing a name or alias with an existing one adds its aliases to it.
+    pub fn import(&mut self, file: &str) -> Result<usize, Box<dyn Error>> {
+        let other = ActorDb::load(file)?;
+        let mut added = 0;
+        for entry in other.actors {
+            let existing = std::iter::once(&entry.name)
+                .chain(entry.aliases.iter())
+                .find_map(|name| self.index.get(&name.trim().to_lowercase()).copied());
+            match existing {
+                Some(i) => {
+                    let actor = &mut self.actors[i];
+                    for name in std::iter::once(entry.name).chain(entry.aliases) {
+                        if name != actor.name && !actor.aliases.contains(&name) {
+                            actor.aliases.push(name);
+                        }
+                    }
+                    if actor.photo.is_empty() {
+                        actor.photo = entry.photo;
+                    }
+                }
+                None => {
+                    self.actors.push(entry);
+                    added += 1;
+                }
+            }
+            self.build_index();
+        }
+        Ok(added)
+    }
+
+    /// Finds the entry for a name or one of its aliases, ignoring case.
+    pub fn find(&self, name: &str) -> Option<&ActorEntry> {
+        self.index
+            .get(&name.trim().to_lowercase())
+            .map(|i| &self.actors[*i])
+    }
+
+    /// The canonical name for an actor, or the name itself when it is not in the database.
+    pub fn canonical_name(&self, name: &str) -> String {
+        self.find(name)
+            .map(|entry| entry.name.clone())
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// A local photo file configured for an actor.
+    pub fn local_photo(&self, name: &str) -> Option<&str> {
+        self.find(name)
+            .map(|entry| entry.photo.as_str())
+            .filter(|photo| photo.is_empty().not() && !photo.starts_with("http"))
+    }
+
+    /// Replaces scraped actor names with their canonical names and drops duplicates.
+    ///
+    /// A photo URL from the database replaces the scraped one.
+    pub fn normalize_actors(&self, actors: &[(String, String)]) -> Vec<(String, String)> {
+        let mut result: Vec<(String, String)> = Vec::new();
+        for (name, photo) in actors {
+            let (name, photo) = match self.find(name) {
+                Some(entry) if entry.photo.starts_with("http") => {
+                    (entry.name.clone(), entry.photo.clone())
+                }
+                Some(entry) => (entry.name.clone(), photo.clone()),
+                None => (name.clone(), photo.clone()),
+            };
+            match result.iter_mut().find(|(n, _)| *n == name) {
+                Some(existing) if existing.1.is_empty() => existing.1 = photo,
+                Some(_) => {}
+                None => result.push((name, photo)),
+            }
+        }
+        result
+    }
+
+    fn build_index(&mut self) {
+        self.index.clear();
+        for (i, actor) in self.actors.iter().enumerate() {
+            for name in std::iter::once(&actor.name).chain(actor.aliases.iter()) {
+                self.index.entry(name.trim().to_lowercase()).or_insert(i);
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::ops::Not;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::artwork::{artwork_ext, normalize_image};
+use crate::config::AppConfig;
+use crate::request::parallel_download_files;
+use crate::sanitize::sanitize_component;
+
+// Index of the library, kept in its root folder
+const INDEX_FILE: &str = ".index.json";
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct PhotoIndex {
+    // actor name -> photo path relative to the library
+    names: HashMap<String, String>,
+    // sha256 of the photo -> photo path relative to the library
+    hashes: HashMap<String, String>,
+}
+
+/// A photo folder shared by every movie, so each actor is downloaded once.
+///
+/// Layouts:
+///
+/// *  `flat`  - `<library>/<name>.jpg`
+/// *  `people`  - `<library>/<first letter>/<name>/folder.jpg`, the Kodi/Jellyfin people folder.
+///
+/// Photos with the same content, such as a site's "no image" placeholder, are stored once.
+pub struct ActorPhotoLibrary {
+    root: PathBuf,
+    layout: String,
+    index: PhotoIndex,
+}
+
+impl ActorPhotoLibrary {
+    /// Opens the library configured in `actor_photo`, or None to use the per-movie `.actors`
+    /// folder.
+    pub fn open(config: &AppConfig) -> Option<ActorPhotoLibrary> {
+        let library = &config.actor_photo.library;
+        if library.is_empty() {
+            return None;
+        }
+        let root = PathBuf::from(library);
+        let index = fs::read_to_string(root.join(INDEX_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Some(ActorPhotoLibrary {
+            root,
+            layout: config.actor_photo.layout.clone(),
+            index,
+        })
+    }
+
+    /// The photo stored for an actor.
+    pub fn lookup(&self, actor_name: &str) -> Option<PathBuf> {
+        self.index
+            .names
+            .get(actor_name)
+            .map(|path| self.root.join(path))
+            .filter(|path| path.is_file())
+    }
+
+    /// Downloads the photos of actors not in the library yet.
+    pub async fn download(
+        &mut self,
+        actors: &[(String, String)],
+        number: &str,
+        config: &AppConfig,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut dn_list = Vec::new();
+        let mut names = Vec::new();
+        for (actor_name, url) in actors.iter() {
+            if url.is_empty() || self.lookup(actor_name).is_some() {
+                continue;
+            }
+            let path = self.photo_path(actor_name, url, config);
+            // 库中已有同名文件但不在索引中，如手动放入的头像
+            if self.root.join(&path).is_file() {
+                self.add(actor_name, &path, false)?;
+                continue;
+            }
+            dn_list.push((url.to_owned(), self.root.join(&path)));
+            names.push((actor_name.to_owned(), path));
+        }
+        if dn_list.is_empty() {
+            return self.save();
+        }
+
+        let result = parallel_download_files(dn_list).await;
+        let mut failed = 0;
+        for ((actor_name, path), r) in names.iter().zip(result.iter()) {
+            match r {
+                Ok(full_path) => {
+                    // 先统一格式，内容相同的头像才能去重
+                    if let Err(e) = normalize_image(full_path, config) {
+                        println!("[-]Image convert failed! {}: {}", full_path.display(), e);
+                    }
+                    self.add(actor_name, path, true)?
+                }
+                Err(_) => failed += 1,
+            }
+        }
+        if failed > 0 {
+            println!(
+                "[-]Failed downloaded {}/{} actor photo for [{}] to '{}', you may retry run mode 3 later.",
+                failed,
+                result.len(),
+                number,
+                self.root.display()
+            );
+        } else {
+            println!(
+                "[+]Successfully downloaded {} actor photo to '{}'.",
+                result.len(),
+                self.root.display()
+            );
+        }
+        self.save()
+    }
+
+    // The path of a new photo relative to the library
+    fn photo_path(&self, actor_name: &str, url: &str, config: &AppConfig) -> PathBuf {
+        let ext = artwork_ext(url, config);
+        match self.layout.as_str() {
+            "people" => {
+                let name = sanitize_component(actor_name, 0, &config.sanitize);
+                let first = name
+                    .chars()
+                    .next()
+                    .unwrap_or('_')
+                    .to_uppercase()
+                    .to_string();
+                let first = sanitize_component(&first, 0, &config.sanitize);
+                Path::new(&first).join(name).join(format!("folder{}", ext))
+            }
+            _ => {
+                let name = sanitize_component(actor_name, ext.len(), &config.sanitize);
+                PathBuf::from(format!("{}{}", name, ext))
+            }
+        }
+    }
+
+    // Records a photo at a path relative to the library. A downloaded photo is dropped
+    // when a photo with the same content is already stored.
+    fn add(&mut self, actor_name: &str, path: &Path, dedupe: bool) -> Result<(), Box<dyn Error>> {
+        let full_path = self.root.join(path);
+        let content = fs::read(&full_path)?;
+        let hash = format!("{:x}", Sha256::digest(&content));
+        let relative = path.to_string_lossy().to_string();
+
+        let stored = self
+            .index
+            .hashes
+            .get(&hash)
+            .filter(|_| dedupe)
+            .filter(|stored| **stored != relative && self.root.join(stored).is_file())
+            .cloned();
+        match stored {
+            Some(stored) => {
+                fs::remove_file(&full_path)?;
+                if let Some(parent) = full_path.parent() {
+                    if parent != self.root && fs::read_dir(parent)?.next().is_none() {
+                        fs::remove_dir(parent)?;
+                    }
+                }
+                self.index.names.insert(actor_name.to_string(), stored);
+            }
+            None => {
+                self.index.hashes.insert(hash, relative.clone());
+                self.index.names.insert(actor_name.to_string(), relative);
+            }
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        if self.index.names.is_empty().not() {
+            fs::create_dir_all(&self.root)?;
+            fs::write(
+                self.root.join(INDEX_FILE),
+                serde_json::to_string_pretty(&self.index)?,
+            )?;
+        }
+        Ok(())
+    }
+}
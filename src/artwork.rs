@@ -0,0 +1,279 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::imageops::FilterType;
+use image::{load_from_memory, open, DynamicImage, GenericImageView, ImageFormat};
+use util::files::file_exit_and_not_empty;
+
+use crate::config::AppConfig;
+use crate::poster::{crop_size, saliency_offset, save_image};
+
+// Bits that may differ between the dhash of images that look the same
+const SIMILAR_DISTANCE: u32 = 6;
+
+// Kodi banner size
+const BANNER_WIDTH: u32 = 1000;
+const BANNER_HEIGHT: u32 = 185;
+
+/// The extension of an image downloaded from the url, `image.format` when it is set.
+pub fn artwork_ext(url: &str, config: &AppConfig) -> String {
+    if config.image.format.is_empty() {
+        crate::core::image_ext(url)
+    } else {
+        format!(".{}", config.image.format)
+    }
+}
+
+/// Re-encodes a downloaded image when its content does not match its extension, such as a WebP
+/// served for a `.jpg` url, or when it is larger than `image.max_width`/`image.max_height`.
+///
+/// Returns whether the image was rewritten.
+pub fn normalize_image(path: &Path, config: &AppConfig) -> Result<bool, Box<dyn Error>> {
+    let target = ImageFormat::from_path(path)?;
+    let data = fs::read(path)?;
+    let format = image::guess_format(&data)?;
+    let max_width = config.image.max_width;
+    let max_height = config.image.max_height;
+    // 格式相同且不限制尺寸时无需解码
+    if format == target && max_width == 0 && max_height == 0 {
+        return Ok(false);
+    }
+
+    let img = load_from_memory(&data)?;
+    let (width, height) = (img.width(), img.height());
+    let too_large = (max_width > 0 && width > max_width) || (max_height > 0 && height > max_height);
+    if format == target && !too_large {
+        return Ok(false);
+    }
+    let img = if too_large {
+        img.resize(
+            if max_width > 0 { max_width } else { u32::MAX },
+            if max_height > 0 { max_height } else { u32::MAX },
+            FilterType::Lanczos3,
+        )
+    } else {
+        img
+    };
+    match target {
+        // JPEG 需去掉透明通道，其它格式直接保存
+        ImageFormat::Jpeg => save_image(&img, path, config.image.jpeg_quality)?,
+        _ => img.save_with_format(path, target)?,
+    }
+    if config.debug_mode.switch {
+        println!(
+            "[+]Image converted {:?} {}x{} to {:?} {}x{}: {}",
+            format,
+            width,
+            height,
+            target,
+            img.width(),
+            img.height(),
+            path.display()
+        );
+    }
+    Ok(true)
+}
+
+/// Makes the artwork in `artwork` from the downloaded cover and extra fanart.
+///
+/// With `fanart_source = "extrafanart"` the sharpest extra fanart replaces the copy of the cover
+/// as fanart. The landscape is cut from the fanart and the banner from the cover.
+pub fn generate_artwork(
+    config: &AppConfig,
+    dir: &str,
+    file_stem: &str,
+    thumb_path: &str,
+    fanart_path: &str,
+    landscape_path: &str,
+    banner_path: &str,
+) {
+    let dir = Path::new(dir);
+    if config.artwork.fanart_source == "extrafanart" && config.extra_fanart.switch {
+        if let Some(sharpest) = sharpest_image(&extra_fanart_files(dir, file_stem, config)) {
+            let full_fanart_path = dir.join(fanart_path);
+            let result = fs::copy(&sharpest, &full_fanart_path)
+                .map_err(|e| e.into())
+                .and_then(|_| normalize_image(&full_fanart_path, config));
+            match result {
+                Ok(_) => println!(
+                    "[+]Fanart from {}",
+                    sharpest.file_name().unwrap_or_default().to_string_lossy()
+                ),
+                Err(e) => println!("[-]Fanart from extrafanart failed: {}", e),
+            }
+        }
+    }
+
+    if config.artwork.landscape {
+        make_image(config, dir, fanart_path, landscape_path, landscape);
+    }
+    if config.artwork.banner {
+        make_image(config, dir, thumb_path, banner_path, banner);
+    }
+}
+
+fn make_image(
+    config: &AppConfig,
+    dir: &Path,
+    source: &str,
+    target: &str,
+    make: fn(&DynamicImage) -> DynamicImage,
+) {
+    let full_target = dir.join(target);
+    if config.common.download_only_missing_images && file_exit_and_not_empty(&full_target) {
+        return;
+    }
+    let img = match open(dir.join(source)) {
+        Ok(img) => img,
+        Err(e) => {
+            println!("[-]Can not make {}: {}", target, e);
+            return;
+        }
+    };
+    match save_image(&make(&img), &full_target, config.image.jpeg_quality) {
+        Ok(()) => println!("[+]Image Generated! {}", target),
+        Err(e) => println!("[-]Can not make {}: {}", target, e),
+    }
+}
+
+// The middle of the image at 16:9
+fn landscape(img: &DynamicImage) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let (crop_width, crop_height) = crop_size(width, height, 16.0 / 9.0);
+    img.crop_imm(
+        (width - crop_width) / 2,
+        (height - crop_height) / 2,
+        crop_width,
+        crop_height,
+    )
+}
+
+// The most detailed strip of the image at the banner aspect ratio
+fn banner(img: &DynamicImage) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let aspect_ratio = BANNER_WIDTH as f32 / BANNER_HEIGHT as f32;
+    let (crop_width, crop_height) = crop_size(width, height, aspect_ratio);
+    let (x, y) = saliency_offset(img, crop_width, crop_height);
+    img.crop_imm(x, y, crop_width, crop_height).resize_exact(
+        BANNER_WIDTH,
+        BANNER_HEIGHT,
+        FilterType::Lanczos3,
+    )
+}
+
+/// The folder extra fanart is saved in for `extra_fanart.layout`.
+pub fn extra_fanart_dir(dir: &Path, config: &AppConfig) -> PathBuf {
+    match config.extra_fanart.layout.as_str() {
+        "backdrops" | "fanart" => dir.to_path_buf(),
+        _ => dir.join(&config.extra_fanart.extra_fanart_folder),
+    }
+}
+
+/// The name of an extra fanart before its number. Backdrops and fanartN sit next to the movie, so
+/// with `image_naming_with_number` they start with the media file stem like the other images.
+pub fn extra_fanart_prefix(file_stem: &str, config: &AppConfig) -> String {
+    let prefix = match config.extra_fanart.layout.as_str() {
+        "backdrops" => "backdrop",
+        "fanart" => "fanart",
+        _ => return "extrafanart-".to_string(),
+    };
+    if config.name_rule.image_naming_with_number {
+        format!("{}-{}", file_stem, prefix)
+    } else {
+        prefix.to_string()
+    }
+}
+
+/// The extra fanart of the movie in `dir`, in order.
+pub fn extra_fanart_files(dir: &Path, file_stem: &str, config: &AppConfig) -> Vec<PathBuf> {
+    numbered_extra_fanart_files(dir, file_stem, config)
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect()
+}
+
+/// The extra fanart of the movie in `dir` with their numbers, in order.
+pub fn numbered_extra_fanart_files(
+    dir: &Path,
+    file_stem: &str,
+    config: &AppConfig,
+) -> Vec<(u32, PathBuf)> {
+    let prefix = extra_fanart_prefix(file_stem, config);
+    let entries = match fs::read_dir(extra_fanart_dir(dir, config)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut files: Vec<(u32, PathBuf)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let n = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.strip_prefix(prefix.as_str()))
+                .and_then(|n| n.parse::<u32>().ok())?;
+            Some((n, path))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+// The image with the highest variance of the Laplacian, blurry frames have few edges
+fn sharpest_image(files: &[PathBuf]) -> Option<PathBuf> {
+    let mut sharpest = None;
+    let mut best = 0.0;
+    for path in files {
+        let sharpness = match open(path) {
+            Ok(img) => sharpness(&img),
+            Err(_) => continue,
+        };
+        if sharpness > best {
+            best = sharpness;
+            sharpest = Some(path.clone());
+        }
+    }
+    sharpest
+}
+
+fn sharpness(img: &DynamicImage) -> f64 {
+    // 缩小后比较，不同尺寸的剧照结果才可比
+    let gray = img.resize(640, 640, FilterType::Triangle).to_luma8();
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+    let pixel = |x: u32, y: u32| gray.get_pixel(x, y)[0] as f64;
+    let mut values = Vec::with_capacity(((width - 2) * (height - 2)) as usize);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            values.push(
+                pixel(x - 1, y) + pixel(x + 1, y) + pixel(x, y - 1) + pixel(x, y + 1)
+                    - 4.0 * pixel(x, y),
+            );
+        }
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+}
+
+/// The difference hash of an image, images that look the same have hashes a few bits apart.
+pub fn dhash(img: &DynamicImage) -> u64 {
+    let gray = img.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if gray.get_pixel(x, y)[0] < gray.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+/// Whether two difference hashes are from images that look the same.
+pub fn similar(a: u64, b: u64) -> bool {
+    (a ^ b).count_ones() <= SIMILAR_DISTANCE
+}
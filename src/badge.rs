@@ -0,0 +1,196 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use image::imageops::{overlay, FilterType};
+use image::{open, DynamicImage, Rgba, RgbaImage};
+
+use crate::config::AppConfig;
+use crate::poster::save_image;
+
+/// The badges a movie gets, from the flags `core_main` found in its file name and metadata.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BadgeFlags {
+    pub sub: bool,
+    pub uncensored: bool,
+    pub is_4k: bool,
+    pub hack: bool,
+}
+
+impl BadgeFlags {
+    // The configured badges that apply, in the configured order
+    fn kinds<'a>(&self, config: &'a AppConfig) -> Vec<&'a str> {
+        config
+            .badge
+            .kinds
+            .iter()
+            .map(|kind| kind.as_str())
+            .filter(|kind| match *kind {
+                "sub" => self.sub,
+                "uncensored" => self.uncensored,
+                "4k" => self.is_4k,
+                "hack" => self.hack,
+                _ => false,
+            })
+            .collect()
+    }
+}
+
+/// Draws the badges of a movie in a corner of the images in `badge.images`.
+///
+/// A badge is `<badge.folder>/<kind>.png` when that file exists, otherwise its label drawn with
+/// `badge.font`. The poster is saved again with `poster.jpeg_quality`, the other images with
+/// `image.jpeg_quality`.
+///
+/// # Arguments
+///
+/// *  `images`  - the image names and their files in `dir` written in this run, such as
+///    `("poster", "poster.jpg")`. Images kept from an earlier run already have their badges.
+pub fn add_badges(config: &AppConfig, dir: &str, images: &[(&str, &str)], flags: BadgeFlags) {
+    if !config.badge.switch {
+        return;
+    }
+    let kinds = flags.kinds(config);
+    if kinds.is_empty() {
+        return;
+    }
+    let font = load_font(&config.badge.font);
+    for (name, file) in images.iter() {
+        if !config.badge.images.iter().any(|image| image == name) {
+            continue;
+        }
+        let path = Path::new(dir).join(file);
+        let quality = if *name == "poster" {
+            config.poster.jpeg_quality
+        } else {
+            config.image.jpeg_quality
+        };
+        match draw_badges(&path, &kinds, font.as_ref(), quality, config) {
+            Ok(()) => println!("[+]Badges {} added to {}", kinds.join(","), file),
+            Err(e) => println!("[-]Add badges to {} failed: {}", file, e),
+        }
+    }
+}
+
+fn load_font(file: &str) -> Option<FontVec> {
+    if file.is_empty() {
+        return None;
+    }
+    let font = fs::read(file)
+        .map_err(|e| e.to_string())
+        .and_then(|data| FontVec::try_from_vec(data).map_err(|e| e.to_string()));
+    match font {
+        Ok(font) => Some(font),
+        Err(e) => {
+            println!("[-]Badge font '{}' load failed: {}", file, e);
+            None
+        }
+    }
+}
+
+fn draw_badges(
+    path: &Path,
+    kinds: &[&str],
+    font: Option<&FontVec>,
+    quality: u8,
+    config: &AppConfig,
+) -> Result<(), Box<dyn Error>> {
+    let mut img = open(path)?.to_rgba8();
+    let (width, height) = img.dimensions();
+    let badge_height = ((height as f32 * config.badge.size).round() as u32).max(8);
+    let margin = badge_height / 4;
+    let right = config.badge.position.ends_with("right");
+    let bottom = config.badge.position.starts_with("bottom");
+
+    let mut x = margin as i64;
+    for kind in kinds {
+        let badge = match badge_image(kind, badge_height, font, config) {
+            Some(badge) => badge,
+            None => {
+                println!("[-]No badge image or font for '{}'", kind);
+                continue;
+            }
+        };
+        let left = if right {
+            width as i64 - x - badge.width() as i64
+        } else {
+            x
+        };
+        let top = if bottom {
+            // 角标比图片高时贴着上边
+            (height as i64 - margin as i64 - badge.height() as i64).max(0)
+        } else {
+            margin as i64
+        };
+        overlay(&mut img, &badge, left, top);
+        x += (badge.width() + margin) as i64;
+    }
+    save_image(&DynamicImage::ImageRgba8(img), path, quality)?;
+    Ok(())
+}
+
+// The user badge scaled to the badge height, or the label drawn with the font
+fn badge_image(
+    kind: &str,
+    badge_height: u32,
+    font: Option<&FontVec>,
+    config: &AppConfig,
+) -> Option<RgbaImage> {
+    let file = Path::new(&config.badge.folder).join(format!("{}.png", kind));
+    if let Ok(badge) = open(&file) {
+        let badge_width = (badge.width() as f32 * badge_height as f32 / badge.height() as f32)
+            .round()
+            .max(1.0) as u32;
+        return Some(
+            badge
+                .resize_exact(badge_width, badge_height, FilterType::Lanczos3)
+                .to_rgba8(),
+        );
+    }
+    let (label, color) = match kind {
+        "sub" => ("字幕", Rgba([220, 50, 50, 230])),
+        "uncensored" => ("无码", Rgba([40, 110, 210, 230])),
+        "4k" => ("4K", Rgba([210, 160, 30, 230])),
+        "hack" => ("破解", Rgba([130, 60, 180, 230])),
+        _ => return None,
+    };
+    font.map(|font| text_badge(label, color, badge_height, font))
+}
+
+// White text on a colored box
+fn text_badge(label: &str, color: Rgba<u8>, badge_height: u32, font: &FontVec) -> RgbaImage {
+    let font = font.as_scaled(PxScale::from(badge_height as f32 * 0.75));
+    let padding = badge_height as f32 * 0.3;
+    let baseline = (badge_height as f32 + font.ascent() + font.descent()) / 2.0;
+
+    let mut caret = padding;
+    let mut glyphs = Vec::new();
+    for c in label.chars() {
+        let mut glyph = font.scaled_glyph(c);
+        glyph.position = point(caret, baseline);
+        caret += font.h_advance(glyph.id);
+        glyphs.push(glyph);
+    }
+
+    let badge_width = (caret + padding).ceil() as u32;
+    let mut badge = RgbaImage::from_pixel(badge_width, badge_height, color);
+    for glyph in glyphs {
+        if let Some(outlined) = font.outline_glyph(glyph) {
+            let bounds = outlined.px_bounds();
+            outlined.draw(|x, y, coverage| {
+                let x = bounds.min.x as i64 + x as i64;
+                let y = bounds.min.y as i64 + y as i64;
+                if x < 0 || y < 0 || x >= badge_width as i64 || y >= badge_height as i64 {
+                    return;
+                }
+                let pixel = badge.get_pixel_mut(x as u32, y as u32);
+                for channel in 0..3 {
+                    let value = pixel[channel] as f32;
+                    pixel[channel] = (value + (255.0 - value) * coverage).round() as u8;
+                }
+            });
+        }
+    }
+    badge
+}
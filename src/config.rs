@@ -10,6 +10,7 @@ use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use crate::actor_db::ActorDb;
 use crate::request::Request;
 use crate::site_search::SiteSearch;
 use crate::strings::{between, insert, substring};
@@ -20,12 +21,42 @@ pub struct AppConfig {
     pub proxy: Proxy,
     pub translate: Translate,
     pub number_extractor: Vec<NumberExtractor>,
+    #[serde(default)]
+    pub number_test: Vec<NumberTest>,
     pub name_rule: NameRule,
     pub uncensored: Uncensored,
     pub debug_mode: DebugMode,
     pub extra_fanart: ExtraFanart,
     pub face: Face,
+    #[serde(default)]
+    pub poster: Poster,
+    #[serde(default)]
+    pub badge: Badge,
+    #[serde(default)]
+    pub image: Image,
+    #[serde(default)]
+    pub artwork: Artwork,
     pub media: Media,
+    #[serde(default)]
+    pub sanitize: Sanitize,
+    #[serde(default)]
+    pub actor_folder: ActorFolder,
+    #[serde(default)]
+    pub actor_db: ActorDatabase,
+    #[serde(default)]
+    pub actor_photo: ActorPhoto,
+    #[serde(default)]
+    pub nfo: Nfo,
+    #[serde(default)]
+    pub output: Output,
+    #[serde(default)]
+    pub library: LibraryIndex,
+    #[serde(default)]
+    pub duplicate: Duplicate,
+    #[serde(default)]
+    pub probe: Probe,
+    #[serde(default)]
+    pub subtitle: Subtitle,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -64,6 +95,7 @@ pub struct Parser {
     pub source_age_check: Option<AgeCheck>,
     pub source_detail_url: Vec<String>,
     pub source_max_user_rating: Option<String>,
+    pub source_country: Option<String>,
     pub source_allow_use_site_number: Option<bool>,
 
     pub expr_number: String,
@@ -71,6 +103,7 @@ pub struct Parser {
     pub expr_outline: String,
     pub expr_actor_name: String,
     pub expr_actor_photo: String,
+    pub expr_actor_gender: Option<String>,
     pub expr_release: String,
     pub expr_runtime: String,
     pub expr_director: String,
@@ -91,6 +124,7 @@ pub struct Parser {
     pub replace_outline: Option<Vec<Rule>>,
     pub replace_actor_name: Option<Vec<Rule>>,
     pub replace_actor_photo: Option<Vec<Rule>>,
+    pub replace_actor_gender: Option<Vec<Rule>>,
     pub replace_release: Option<Vec<Rule>>,
     pub replace_runtime: Option<Vec<Rule>>,
     pub replace_director: Option<Vec<Rule>>,
@@ -110,9 +144,38 @@ pub struct NumberExtractor {
     pub name: String,
     pub regex: String,
     pub sources: Option<Vec<String>>,
+    pub normalize: Option<NumberNormalize>,
+    #[serde(skip)]
+    compiled_regex: Option<Regex>,
+}
+
+/// Steps turning a matched number into the canonical form used for folders and NFOs.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct NumberNormalize {
+    #[serde(default)]
+    pub uppercase: bool,
+    /// Replace `_` and spaces with `-` and insert a dash between a letters-only prefix and 2 to 5
+    /// digits, so `FC21234567` is left alone.
+    #[serde(default)]
+    pub dash: bool,
+    /// Zero-pad the trailing digits to this width, 0 disables padding.
+    #[serde(default)]
+    pub zero_pad: usize,
+    /// A regex removed from the start of the number, e.g. the `h_346` label prefix.
+    pub strip_prefix: Option<String>,
+    #[serde(skip)]
+    compiled_strip_prefix: Option<Regex>,
 }
 
 impl NumberExtractor {
+    pub fn new(name: &str, regex: &str) -> Self {
+        NumberExtractor {
+            name: name.to_string(),
+            regex: regex.to_string(),
+            ..Default::default()
+        }
+    }
+
     /// Gets the number from a given filename based on a regex.
     ///
     /// # Arguments
@@ -124,23 +187,269 @@ impl NumberExtractor {
     /// An Option of a tuple of strings containing the number and the name.
     /// If the regex does not match the filename, None is returned.
     pub fn get_number(&self, filename: &str) -> Option<String> {
-        let re = Regex::new(&self.regex).unwrap();
-        if re.is_match(filename) {
-            let m = re.captures(filename).unwrap();
-            return Some(m.get(0).unwrap().as_str().to_string());
+        let m = match &self.compiled_regex {
+            Some(re) => re.find(filename),
+            None => Regex::new(&self.regex).ok()?.find(filename),
+        };
+        m.map(|m| m.as_str().to_string())
+    }
+
+    /// Compiles the regexes of the extractor once so they are not rebuilt for every file.
+    pub fn compile(&mut self) -> Result<(), Box<dyn Error>> {
+        let re = Regex::new(&self.regex).map_err(|e| {
+            format!(
+                "[-]Invalid regex of number extractor '{}': {}",
+                self.name, e
+            )
+        })?;
+        self.compiled_regex = Some(re);
+        if let Some(normalize) = self.normalize.as_mut() {
+            if let Some(strip_prefix) = &normalize.strip_prefix {
+                let re = Regex::new(strip_prefix).map_err(|e| {
+                    format!(
+                        "[-]Invalid strip_prefix of number extractor '{}': {}",
+                        self.name, e
+                    )
+                })?;
+                normalize.compiled_strip_prefix = Some(re);
+            }
         }
-        None
+        Ok(())
+    }
+
+    /// Normalizes a number matched by this extractor into its canonical form.
+    ///
+    /// The number is returned unchanged when the extractor has no `normalize` settings.
+    pub fn normalize_number(&self, number: &str) -> String {
+        let normalize = match &self.normalize {
+            Some(normalize) => normalize,
+            None => return number.to_string(),
+        };
+        let mut result = number.trim().to_string();
+        let strip_prefix = match &normalize.compiled_strip_prefix {
+            Some(re) => Some(re.clone()),
+            None => normalize
+                .strip_prefix
+                .as_ref()
+                .and_then(|strip_prefix| Regex::new(strip_prefix).ok()),
+        };
+        if let Some(re) = strip_prefix {
+            result = re
+                .replace(&result, "")
+                .trim_start_matches(['-', '_'])
+                .to_string();
+        }
+        if normalize.dash {
+            result = result.replace(['_', ' '], "-");
+            if let Some(m) = DASH_REGEX.captures(&result) {
+                result = format!("{}-{}", &m[1], &m[2]);
+            }
+        }
+        if normalize.zero_pad > 0 {
+            if let Some(m) = DIGITS_REGEX.captures(&result) {
+                result = format!("{}{:0>width$}", &m[1], &m[2], width = normalize.zero_pad);
+            }
+        }
+        if normalize.uppercase {
+            result = result.to_uppercase();
+        }
+        result
     }
 }
 
+/// A file name and the number it is expected to be extracted as, used by `extract --self-test`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct NumberTest {
+    pub file: String,
+    pub number: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct NameRule {
     pub location_rule: String,
     pub naming_rule: String,
+    pub media_rule: Option<String>,
     pub max_title_len: usize,
     pub image_naming_with_number: bool,
 }
 
+impl NameRule {
+    /// The template of the media file name, defaults to `$number`.
+    pub fn media_rule(&self) -> &str {
+        self.media_rule.as_deref().unwrap_or("$number")
+    }
+}
+
+/// How generated folder and file names are made safe for the filesystem.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Sanitize {
+    /// Replaces characters that are not allowed in file names.
+    pub replacement: String,
+    /// Per-character replacements applied before `replacement`, e.g. `"/" = "／"`.
+    pub replace_chars: HashMap<String, String>,
+    /// The maximum length of a single path component in bytes.
+    pub max_component_bytes: usize,
+}
+
+impl Default for Sanitize {
+    fn default() -> Self {
+        Sanitize {
+            replacement: "_".to_string(),
+            replace_chars: HashMap::new(),
+            max_component_bytes: 255,
+        }
+    }
+}
+
+/// How the actors of a movie are turned into the `$actor` folder name.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ActorFolder {
+    /// `all` joins every actor, `first` keeps the first `max_actors`, `main` keeps the main actress
+    /// only.
+    pub mode: String,
+    /// With `all`, more actors than this use the collective label; with `first`, how many are kept.
+    /// 0 means no limit.
+    pub max_actors: usize,
+    /// Joined names longer than this many bytes use the collective label.
+    pub max_len: usize,
+    pub collective_label: String,
+    pub separator: String,
+    /// Maps actor names to the name used for the folder.
+    pub aliases: HashMap<String, String>,
+}
+
+impl Default for ActorFolder {
+    fn default() -> Self {
+        ActorFolder {
+            mode: "all".to_string(),
+            max_actors: 0,
+            max_len: 50,
+            collective_label: "多人作品".to_string(),
+            separator: ", ".to_string(),
+            aliases: HashMap::new(),
+        }
+    }
+}
+
+/// The local actor database that maps aliases to a canonical name and photo.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ActorDatabase {
+    pub switch: bool,
+    /// The JSON file holding the actors, see `actor_db::ActorDb`.
+    pub file: String,
+}
+
+impl Default for ActorDatabase {
+    fn default() -> Self {
+        ActorDatabase {
+            switch: false,
+            file: "./actors.json".to_string(),
+        }
+    }
+}
+
+/// Where actor photos are stored, see `actor_photo::ActorPhotoLibrary`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ActorPhoto {
+    /// A folder shared by every movie, empty to use the `.actors` folder of each movie.
+    pub library: String,
+    /// `flat` or `people`.
+    pub layout: String,
+}
+
+impl Default for ActorPhoto {
+    fn default() -> Self {
+        ActorPhoto {
+            library: String::new(),
+            layout: "flat".to_string(),
+        }
+    }
+}
+
+/// How NFO files are written.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Nfo {
+    /// `kodi`, `jellyfin`, `emby` or `plex` (for the XBMCnfoMoviesImporter agent).
+    pub dialect: String,
+    /// Merge with an existing NFO instead of overwriting it.
+    pub merge: bool,
+    /// Leave an NFO untouched when it has `<lockdata>true</lockdata>`.
+    pub honor_lockdata: bool,
+    /// NFO elements whose existing value is kept when merging, on top of the Emby locked fields.
+    pub keep_fields: Vec<String>,
+}
+
+impl Default for Nfo {
+    fn default() -> Self {
+        Nfo {
+            dialect: "kodi".to_string(),
+            merge: true,
+            honor_lockdata: true,
+            keep_fields: Vec::new(),
+        }
+    }
+}
+
+/// Which metadata files are written for a movie, see `output::write_outputs`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Output {
+    /// Any of `nfo`, `json` and `plex`.
+    pub writers: Vec<String>,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output {
+            writers: vec!["nfo".to_string()],
+        }
+    }
+}
+
+/// The SQLite index of processed movies, see `library::Library`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LibraryIndex {
+    pub switch: bool,
+    pub file: String,
+}
+
+impl Default for LibraryIndex {
+    fn default() -> Self {
+        LibraryIndex {
+            switch: false,
+            file: "./library.db".to_string(),
+        }
+    }
+}
+
+/// What happens to a movie whose number is already in the output, see
+/// `duplicate::handle_duplicate`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Duplicate {
+    /// `skip`, `move`, `keep_larger`, `keep_higher_resolution` or `version`.
+    pub policy: String,
+    pub folder: String,
+    /// Same-number files whose durations differ more than this many seconds are kept as versions.
+    pub max_duration_diff: f64,
+}
+
+impl Default for Duplicate {
+    fn default() -> Self {
+        Duplicate {
+            policy: "skip".to_string(),
+            folder: "duplicates".to_string(),
+            max_duration_diff: 60.0,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Uncensored {
     pub uncensored_prefix: String,
@@ -169,17 +478,215 @@ pub struct Common {
     pub ignore_failed_list: bool,
     pub download_only_missing_images: bool,
     pub sleep: u64,
+    pub number_override_file: Option<String>,
 }
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+
+impl Common {
+    /// The name of the per-folder file overriding extracted numbers.
+    pub fn number_override_file(&self) -> &str {
+        self.number_override_file.as_deref().unwrap_or(".number")
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
 pub struct ExtraFanart {
     pub switch: bool,
     pub extra_fanart_folder: String,
+    /// `folder` saves `<extra_fanart_folder>/extrafanart-N`, `backdrops` saves `backdropN` and
+    /// `fanart` saves `fanartN` next to the movie.
+    pub layout: String,
+    /// The most extra fanart kept, 0 is no limit.
+    pub max_count: usize,
+    /// Drop frames that look the same as the cover or a kept frame.
+    pub dedupe: bool,
+    /// Smaller frames are dropped.
+    pub min_width: u32,
+    pub min_height: u32,
+}
+
+impl Default for ExtraFanart {
+    fn default() -> Self {
+        ExtraFanart {
+            switch: false,
+            extra_fanart_folder: "extrafanart".to_string(),
+            layout: "folder".to_string(),
+            max_count: 0,
+            dedupe: true,
+            min_width: 0,
+            min_height: 0,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Face {
+    /// Detectors tried in order, `hog` and `cnn` separated by `,`.
     pub locations_model: String,
+    /// Which face a poster is centered on: `largest`, `central` or `all`.
+    pub selection: Option<String>,
+    /// The CNN model file, the model bundled with dlib when not set.
+    pub cnn_model: Option<String>,
+}
+
+impl Face {
+    pub fn selection(&self) -> &str {
+        self.selection.as_deref().unwrap_or("largest")
+    }
+}
+
+/// How the poster is cut from the cover, see `poster::cut_image`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Poster {
+    /// Strategies tried in order: `face`, `right`, `center` and `saliency`.
+    pub strategy: String,
+    /// Width / height of the poster.
     pub aspect_ratio: f32,
+    /// The poster size in pixels, 0 keeps the cropped size or follows the aspect ratio.
+    pub width: u32,
+    pub height: u32,
+    pub jpeg_quality: u8,
+}
+
+impl Default for Poster {
+    fn default() -> Self {
+        Poster {
+            strategy: "face,right".to_string(),
+            aspect_ratio: 2.0 / 3.0,
+            width: 0,
+            height: 0,
+            jpeg_quality: 95,
+        }
+    }
+}
+
+/// How downloaded images are stored, see `artwork::normalize_image`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Image {
+    /// `jpg` or `png` for every image, empty keeps the extension of the URL.
+    pub format: String,
+    /// Larger images are shrunk to fit, 0 is no limit.
+    pub max_width: u32,
+    pub max_height: u32,
+    pub jpeg_quality: u8,
+}
+
+impl Default for Image {
+    fn default() -> Self {
+        Image {
+            format: String::new(),
+            max_width: 0,
+            max_height: 0,
+            jpeg_quality: 95,
+        }
+    }
+}
+
+/// Media file probing with ffprobe, see `probe::probe`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Probe {
+    pub switch: bool,
+    pub ffprobe: String,
+    /// Add resolution and HDR tags to the NFO.
+    pub tags: bool,
+}
+
+impl Default for Probe {
+    fn default() -> Self {
+        Probe {
+            switch: false,
+            ffprobe: "ffprobe".to_string(),
+            tags: true,
+        }
+    }
+}
+
+/// How a movie with Chinese subtitles is found, see `subtitle::chinese_subtitle`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Subtitle {
+    /// Words in the file name of a movie with subtitles, hard-coded or not.
+    pub markers: Vec<String>,
+    /// Check the subtitle tracks of the movie with ffprobe.
+    pub embedded: bool,
+    /// Check the subtitle files next to the movie.
+    pub external: bool,
+    /// Language tags of Chinese subtitle tracks and files.
+    pub languages: Vec<String>,
+}
+
+impl Default for Subtitle {
+    fn default() -> Self {
+        let to_strings = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+        Subtitle {
+            markers: to_strings(&["中文", "字幕", "中字"]),
+            embedded: false,
+            external: true,
+            languages: to_strings(&[
+                "chi", "zho", "chs", "cht", "zh", "sc", "tc", "简体", "繁体", "简中", "繁中",
+            ]),
+        }
+    }
+}
+
+/// Artwork made from the cover and the extra fanart, see `artwork::generate_artwork`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Artwork {
+    /// A 16:9 landscape cut from the fanart.
+    pub landscape: bool,
+    /// A 1000x185 banner cut from the cover.
+    pub banner: bool,
+    /// `cover` copies the cover, `extrafanart` uses the sharpest extra fanart.
+    pub fanart_source: String,
+}
+
+impl Default for Artwork {
+    fn default() -> Self {
+        Artwork {
+            landscape: false,
+            banner: false,
+            fanart_source: "cover".to_string(),
+        }
+    }
+}
+
+/// Corner badges on the artwork, see `badge::add_badges`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Badge {
+    pub switch: bool,
+    /// Badges to show: `sub`, `uncensored`, `4k` and `hack`.
+    pub kinds: Vec<String>,
+    /// Images that get badges: `poster` and `thumb`.
+    pub images: Vec<String>,
+    /// `top_left`, `top_right`, `bottom_left` or `bottom_right`, badges are lined up from there.
+    pub position: String,
+    /// Badge height as a fraction of the image height.
+    pub size: f32,
+    /// Folder of user badges named `<kind>.png`.
+    pub folder: String,
+    /// Font used to draw badges without a PNG.
+    pub font: String,
+}
+
+impl Default for Badge {
+    fn default() -> Self {
+        Badge {
+            switch: false,
+            kinds: ["sub", "uncensored", "4k", "hack"]
+                .iter()
+                .map(|kind| kind.to_string())
+                .collect(),
+            images: vec!["poster".to_string()],
+            position: "top_left".to_string(),
+            size: 0.08,
+            folder: "./badges".to_string(),
+            font: String::new(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -271,6 +778,9 @@ impl StringFlow {
 }
 
 lazy_static! {
+    // 只在纯字母前缀和 2 到 5 位数字之间加 -，FC21234567 等更长的数字可能含有前缀的一部分
+    static ref DASH_REGEX: Regex = Regex::new(r"^([A-Za-z]+)(\d{2,5})$").unwrap();
+    static ref DIGITS_REGEX: Regex = Regex::new(r"^(.*?)(\d+)$").unwrap();
     pub static ref CONFIG: Arc<RwLock<AppConfig>> = Arc::new(RwLock::new(AppConfig::default()));
     static ref SOURCES: RwLock<HashMap<String, Parser>> = RwLock::new(HashMap::new());
 }
@@ -281,7 +791,10 @@ impl AppConfig {
             .add_source(config::File::from(Path::new(file)))
             .build()
             .unwrap_or_else(|_| panic!("[!] Fail to load config file {}", file));
-        let cfg = settings.try_deserialize::<AppConfig>().unwrap();
+        let mut cfg = settings.try_deserialize::<AppConfig>().unwrap();
+        for extractor in cfg.number_extractor.iter_mut() {
+            extractor.compile()?;
+        }
 
         let config_clone = CONFIG.clone();
         {
@@ -289,7 +802,10 @@ impl AppConfig {
             *config = cfg;
         }
         let config = CONFIG.read().unwrap();
-        config.init_sources();
+        config.init_sources()?;
+        if config.actor_db.switch {
+            ActorDb::init(&config.actor_db.file)?;
+        }
         if config.proxy.switch {
             Request::set_proxy(&config.proxy).await?;
         }
@@ -301,7 +817,7 @@ impl AppConfig {
         CONFIG.read().unwrap()
     }
 
-    pub fn init_sources(&self) {
+    pub fn init_sources(&self) -> Result<(), Box<dyn Error>> {
         let parsers = Config::builder()
             .add_source(
                 glob((self.common.parser_folder.to_string() + "/*.toml").as_str())
@@ -317,9 +833,18 @@ impl AppConfig {
                 )
             });
         let sources_holder = parsers.try_deserialize::<SourcesHolder>().unwrap();
+        let errors: Vec<String> = sources_holder
+            .sources
+            .values()
+            .filter_map(|parser| parser.prepare().err())
+            .collect();
+        if !errors.is_empty() {
+            return Err(format!("[-]Invalid xpath in parsers:\n{}", errors.join("\n")).into());
+        }
         for ele in sources_holder.sources {
             SOURCES.write().unwrap().insert(ele.0, ele.1);
         }
+        Ok(())
     }
 
     pub fn get_sources(&self) -> std::sync::RwLockReadGuard<HashMap<String, Parser>> {
@@ -366,6 +891,39 @@ impl AppConfig {
         if ![1, 2, 3].contains(&main_mode) {
             return Err("[-] Main mode must be 1 or 2 or 3!".into());
         }
+        for writer in self.output.writers.iter() {
+            if !["nfo", "json", "plex"].contains(&writer.as_str()) {
+                return Err(format!("[-] Unknown output writer '{}'!", writer).into());
+            }
+        }
+        if !["", "jpg", "png"].contains(&self.image.format.as_str()) {
+            return Err(format!("[-] Unsupported image format '{}'!", self.image.format).into());
+        }
+        if !["folder", "backdrops", "fanart"].contains(&self.extra_fanart.layout.as_str()) {
+            return Err(format!(
+                "[-] Unknown extra fanart layout '{}'!",
+                self.extra_fanart.layout
+            )
+            .into());
+        }
+        if !["cover", "extrafanart"].contains(&self.artwork.fanart_source.as_str()) {
+            return Err(format!(
+                "[-] Unknown fanart source '{}'!",
+                self.artwork.fanart_source
+            )
+            .into());
+        }
+        let positions = ["top_left", "top_right", "bottom_left", "bottom_right"];
+        if self.badge.switch && !positions.contains(&self.badge.position.as_str()) {
+            return Err(format!("[-] Unknown badge position '{}'!", self.badge.position).into());
+        }
+        if self.badge.switch && !(self.badge.size > 0.0 && self.badge.size <= 0.5) {
+            return Err(format!(
+                "[-] Badge size {} is out of range (0, 0.5]!",
+                self.badge.size
+            )
+            .into());
+        }
         Ok(())
     }
 
@@ -5,50 +5,101 @@ use std::fs::{hard_link, OpenOptions};
 use std::io::Write;
 use std::ops::Not;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 
 use chrono::Local;
-use dlib_face_recognition::{
-    FaceDetector, FaceDetectorCnn, FaceDetectorTrait, FaceLocations, ImageMatrix,
-};
-use image::{open, DynamicImage};
-use quick_xml::se::to_string;
-use serde::Serialize;
-use walkdir::WalkDir;
-use xmlem::{display, Document};
+use lazy_static::lazy_static;
+use regex::Regex;
 use util::files::{create_soft_link, file_exit_and_not_empty};
+use walkdir::WalkDir;
 
+use crate::actor_photo::ActorPhotoLibrary;
+use crate::artwork::{
+    artwork_ext, dhash, extra_fanart_dir, extra_fanart_prefix, generate_artwork, normalize_image,
+    numbered_extra_fanart_files, similar,
+};
+use crate::badge::{add_badges, BadgeFlags};
 use crate::config::{AppConfig, NumberExtractor};
+use crate::duplicate::{handle_duplicate, Duplicate};
+use crate::library::{Library, LibraryEntry};
+use crate::naming::Naming;
+use crate::nfo::{nfo_path, NfoContext};
 use crate::number_parser::get_number;
-use crate::parser::{Actor, Movie, Tag};
+use crate::output::write_outputs;
+use crate::parser::Movie;
+use crate::poster::cut_image;
+use crate::probe::probe;
 use crate::request::{download_file, parallel_download_files};
+use crate::sanitize::sanitize_component;
 use crate::scraping::Scraping;
+use crate::subtitle::chinese_subtitle;
 
+lazy_static! {
+    static ref UNCENSORED_REGEX: Regex = Regex::new(r"[\d-]{4,}|\d{6}_\d{2,3}|(cz|gedo|k|n|red-|se)\d{2,4}|heyzo.+|xxx-av-.+|heydouga-.+|x-art\.\d{2}\.\d{2}\.\d{2}").unwrap();
+}
+
+/// Scrapes a movie, or takes the already scraped `scraped`, then moves it and writes its metadata.
 pub async fn core_main(
     file_path: &str,
     number_extractor: &NumberExtractor,
     custom_number: &str,
+    scraped: Option<Movie>,
     sources: Option<String>,
     specified_source: Option<String>,
     config: &AppConfig,
 ) -> Result<(), Box<dyn Error>> {
-    let mut scraping = Scraping::new(config);
-    let movie = scraping
-        .search(custom_number, number_extractor, sources, specified_source)
-        .await;
+    let movie = match scraped {
+        Some(movie) => Some(movie),
+        None => {
+            let mut scraping = Scraping::new(config);
+            scraping
+                .search(custom_number, number_extractor, sources, specified_source)
+                .await
+        }
+    };
 
     if movie.is_none() {
-        move_failed_folder(file_path, config);
+        // 移动前读取文件大小，记录移动后的路径
+        let entry = library_entry(file_path);
+        let failed_path = move_failed_folder(file_path, config);
+        if config.library.switch {
+            let entry = LibraryEntry {
+                number: custom_number.to_string(),
+                file_path: file_path.to_string(),
+                media_path: failed_path,
+                status: "failed".to_string(),
+                ..entry
+            };
+            record_library(config, &entry);
+        }
         return Ok(());
     }
-    let movie = movie.unwrap();
+    let mut movie = movie.unwrap();
+    if number_extractor.normalize.is_some() {
+        // 文件夹和NFO使用规范化后的番号，数据源仍使用原始番号
+        movie.number = number_extractor.normalize_number(custom_number);
+    }
+    // 读取影片的实际分辨率、时长和字幕轨道
+    let probed = if config.probe.switch || config.subtitle.embedded {
+        probe(Path::new(file_path), &config.probe.ffprobe)
+    } else {
+        None
+    };
+    let media_info = probed.clone().filter(|_| config.probe.switch);
+    if let Some(runtime) = media_info.as_ref().and_then(|info| info.runtime()) {
+        if movie.runtime.is_empty() {
+            movie.runtime = runtime;
+        }
+    }
+
     let number = &movie.number;
     // =======================================================================判断-C,-CD后缀
-    let cn_sub = regex::Regex::new(r"[-_]C(\.\w+$|-\w+)|\d+ch(\.\w+$|-\w+)")
-        .unwrap()
-        .is_match(file_path)
-        || file_path.contains("中文")
-        || file_path.contains("字幕");
+    let subtitle_source = chinese_subtitle(file_path, probed.as_ref(), config);
+    if let Some(source) = subtitle_source {
+        if config.debug_mode.switch {
+            println!("[+]Chinese subtitle found: {:?}", source);
+        }
+    }
+    let cn_sub = subtitle_source.is_some();
     let c_word = if cn_sub { "-C" } else { "" }; // 中文字幕影片后缀
 
     // 判断是否无码
@@ -74,135 +125,237 @@ pub async fn core_main(
     };
 
     // 判断是否4k
-    let _4k = lower_path.contains("4k");
+    let _4k = match &media_info {
+        Some(info) if info.width > 0 => info.is_4k(),
+        _ => lower_path.contains("4k"),
+    };
+
+    let badges = BadgeFlags {
+        sub: cn_sub,
+        uncensored: uncensored || _leak,
+        is_4k: _4k,
+        hack: _hack,
+    };
+
+    let mut naming = Naming::new(&movie, file_path, leak_word, c_word, hack_word, config);
+    if let Some(info) = &media_info {
+        if naming.get("resolution").is_empty() {
+            naming.set("resolution", &info.resolution());
+        }
+    }
+    let mut file_stem = naming.file_stem(config);
+    if config.common.main_mode != 3 {
+        // 同一番号已存在时按 duplicate.policy 处理
+        let path = folder_path(&naming, config);
+        let info = media_info.as_ref();
+        let skipped = match handle_duplicate(file_path, &path, number, info, &mut naming, config) {
+            Ok(Duplicate::Keep(stem)) => {
+                file_stem = stem;
+                None
+            }
+            Ok(Duplicate::Skip(media_path)) => Some(media_path),
+            Err(e) => {
+                println!(
+                    "[-]Duplicate check failed, '{}' is left in place: {}",
+                    file_path, e
+                );
+                Some(PathBuf::from(file_path))
+            }
+        };
+        if let Some(media_path) = skipped {
+            if config.library.switch {
+                let media_path = media_path.to_string_lossy();
+                let entry = LibraryEntry {
+                    status: "skipped".to_string(),
+                    ..scraped_entry(&movie, file_path, &media_path, "", "", "")
+                };
+                record_library(config, &entry);
+            }
+            return Ok(());
+        }
+    }
 
     let cover = &movie.cover;
-    let ext = image_ext(cover.as_str());
+    let ext = artwork_ext(cover, config);
     let mut thumb_path = format!("thumb{}", ext);
     let mut poster_path = format!("poster{}", ext);
     let mut fanart_path = format!("fanart{}", ext);
+    let mut landscape_path = format!("landscape{}", ext);
+    let mut banner_path = format!("banner{}", ext);
     if config.name_rule.image_naming_with_number {
-        fanart_path = format!(
-            "{}{}{}{}-fanart{}",
-            number, leak_word, c_word, hack_word, ext
-        );
-        poster_path = format!(
-            "{}{}{}{}-poster{}",
-            number, leak_word, c_word, hack_word, ext
-        );
-        thumb_path = format!(
-            "{}{}{}{}-thumb{}",
-            number, leak_word, c_word, hack_word, ext
-        );
+        fanart_path = format!("{}-fanart{}", file_stem, ext);
+        poster_path = format!("{}-poster{}", file_stem, ext);
+        thumb_path = format!("{}-thumb{}", file_stem, ext);
+        landscape_path = format!("{}-landscape{}", file_stem, ext);
+        banner_path = format!("{}-banner{}", file_stem, ext);
     }
 
     match config.common.main_mode {
         1 => {
             // 创建文件夹
-            let path = create_folder(&movie, config);
+            let path = create_folder(&naming, config);
             let path_str = path.to_string_lossy();
             let dir = path_str.as_ref();
 
+            let mut new_poster = false;
             if movie.cover_small.is_empty().not() {
-                download_small_cover(&movie.cover_small, dir, &poster_path, config).await;
+                new_poster =
+                    download_small_cover(&movie.cover_small, dir, &poster_path, config).await;
             }
 
             let cover = movie.cover.clone();
-            download_cover(&cover, dir, &thumb_path, &fanart_path, config).await;
+            let new_thumb = download_cover(&cover, dir, &thumb_path, &fanart_path, config).await;
 
             if config.extra_fanart.switch {
                 let extra_fanart = &movie.extra_fanart;
-                download_extra_fanart(extra_fanart, dir, config).await;
+                download_extra_fanart(extra_fanart, dir, &file_stem, &thumb_path, config).await;
             }
 
             download_actor_photo(&movie.actor, dir, number, config).await;
 
             if movie.cover_small.is_empty() {
-                cut_image(config, dir, &thumb_path, &poster_path);
+                new_poster = cut_image(config, dir, &thumb_path, &poster_path);
             }
-
-            paste_file_to_folder(
-                file_path,
-                dir,
-                number.as_str(),
-                leak_word,
-                c_word,
-                hack_word,
-                config,
-            )
-            .await?;
-
-            write_nfo_file(
+            generate_artwork(
                 config,
-                &movie,
                 dir,
-                leak_word,
-                c_word,
-                hack_word,
-                _4k,
-                uncensored,
-                file_path,
+                &file_stem,
                 &thumb_path,
-                &poster_path,
                 &fanart_path,
-            )
-            .await?;
+                &landscape_path,
+                &banner_path,
+            );
+            // 只给本次写入的图片加角标，保留下来的图片已经有角标
+            let mut images = Vec::new();
+            if new_poster {
+                images.push(("poster", poster_path.as_str()));
+            }
+            if new_thumb {
+                images.push(("thumb", thumb_path.as_str()));
+            }
+            add_badges(config, dir, &images, badges);
+
+            let media_path = paste_file_to_folder(file_path, dir, &file_stem, config).await?;
+
+            let ctx = NfoContext {
+                movie: &movie,
+                naming: &naming,
+                dir,
+                file_path,
+                uncensored,
+                is_4k: _4k,
+                thumb_path: &thumb_path,
+                poster_path: &poster_path,
+                fanart_path: &fanart_path,
+                landscape_path: &landscape_path,
+                banner_path: &banner_path,
+                media_info: media_info.as_ref(),
+            };
+            let written = write_outputs(config, &ctx).await;
+            if config.library.switch {
+                let entry = LibraryEntry {
+                    nfo_path: nfo_path(config, &ctx).to_string_lossy().to_string(),
+                    ..scraped_entry(
+                        &movie,
+                        file_path,
+                        &media_path,
+                        dir,
+                        &poster_path,
+                        &fanart_path,
+                    )
+                };
+                record_library(config, &written_entry(entry, &written));
+            }
+            report_written(file_path, written);
         }
         2 => {
             // 创建文件夹
-            let path = create_folder(&movie, config);
+            let path = create_folder(&naming, config);
             let path_str = path.to_string_lossy();
             let dir = path_str.as_ref();
-            paste_file_to_folder(
-                file_path,
-                dir,
-                number.as_str(),
-                leak_word,
-                c_word,
-                hack_word,
-                config,
-            )
-            .await?;
+            let media_path = paste_file_to_folder(file_path, dir, &file_stem, config).await?;
+            if config.library.switch {
+                let entry = LibraryEntry {
+                    status: "moved".to_string(),
+                    ..scraped_entry(&movie, file_path, &media_path, dir, "", "")
+                };
+                record_library(config, &entry);
+            }
         }
         3 => {
             // 创建文件夹
-            let path = create_folder(&movie, config);
+            let path = create_folder(&naming, config);
             let path_str = path.to_string_lossy();
             let dir = path_str.as_ref();
 
+            let mut new_poster = false;
             if movie.cover_small.is_empty().not() {
-                download_small_cover(&movie.cover_small, dir, &poster_path, config).await;
+                new_poster =
+                    download_small_cover(&movie.cover_small, dir, &poster_path, config).await;
             }
 
             let cover = movie.cover.clone();
-            download_cover(&cover, dir, &thumb_path, &fanart_path, config).await;
+            let new_thumb = download_cover(&cover, dir, &thumb_path, &fanart_path, config).await;
 
             if config.extra_fanart.switch {
                 let extra_fanart = &movie.extra_fanart;
-                download_extra_fanart(extra_fanart, dir, config).await;
+                download_extra_fanart(extra_fanart, dir, &file_stem, &thumb_path, config).await;
             }
 
             download_actor_photo(&movie.actor, dir, number, config).await;
 
             if movie.cover_small.is_empty() {
-                cut_image(config, dir, &thumb_path, &poster_path);
+                new_poster = cut_image(config, dir, &thumb_path, &poster_path);
             }
-
-            write_nfo_file(
+            generate_artwork(
                 config,
-                &movie,
                 dir,
-                leak_word,
-                c_word,
-                hack_word,
-                _4k,
-                uncensored,
-                file_path,
+                &file_stem,
                 &thumb_path,
-                &poster_path,
                 &fanart_path,
-            )
-            .await?;
+                &landscape_path,
+                &banner_path,
+            );
+            // 只给本次写入的图片加角标，保留下来的图片已经有角标
+            let mut images = Vec::new();
+            if new_poster {
+                images.push(("poster", poster_path.as_str()));
+            }
+            if new_thumb {
+                images.push(("thumb", thumb_path.as_str()));
+            }
+            add_badges(config, dir, &images, badges);
+
+            let ctx = NfoContext {
+                movie: &movie,
+                naming: &naming,
+                dir,
+                file_path,
+                uncensored,
+                is_4k: _4k,
+                thumb_path: &thumb_path,
+                poster_path: &poster_path,
+                fanart_path: &fanart_path,
+                landscape_path: &landscape_path,
+                banner_path: &banner_path,
+                media_info: media_info.as_ref(),
+            };
+            let written = write_outputs(config, &ctx).await;
+            if config.library.switch {
+                let entry = LibraryEntry {
+                    nfo_path: nfo_path(config, &ctx).to_string_lossy().to_string(),
+                    ..scraped_entry(
+                        &movie,
+                        file_path,
+                        file_path,
+                        dir,
+                        &poster_path,
+                        &fanart_path,
+                    )
+                };
+                record_library(config, &written_entry(entry, &written));
+            }
+            report_written(file_path, written);
         }
         _ => {}
     }
@@ -210,41 +363,88 @@ pub async fn core_main(
     Ok(())
 }
 
-fn create_folder(movie: &Movie, config: &AppConfig) -> PathBuf {
-    let success_folder = config.common.success_output_folder.as_str();
-    let actor_names = &movie
-        .actor
-        .iter()
-        .map(|(name, _)| name.to_string())
-        .collect::<Vec<String>>()
-        .join(", ");
-    let mut location_rule = config.name_rule.location_rule.clone();
-
-    if location_rule.contains("$actor") {
-        let new_rule = if actor_names.len() > 50 {
-            location_rule.replace("$actor", "多人作品")
-        } else {
-            location_rule.replace("$actor", actor_names.as_str())
-        };
-        location_rule = new_rule;
+// 影片已放入输出文件夹，NFO 写入失败时只记为失败，不再移动影片
+fn written_entry(entry: LibraryEntry, written: &Result<(), Box<dyn Error>>) -> LibraryEntry {
+    match written {
+        Ok(()) => entry,
+        Err(_) => LibraryEntry {
+            nfo_path: String::new(),
+            status: "failed".to_string(),
+            ..entry
+        },
     }
-    let max_len = config.name_rule.max_title_len;
-    let title = &movie.title;
-    if config.name_rule.location_rule.contains("title") && title.len() > max_len {
-        let title = if title.len() > max_len {
-            &title[..max_len]
+}
+
+fn report_written(file_path: &str, written: Result<(), Box<dyn Error>>) {
+    if let Err(e) = written {
+        eprintln!("[-] [{}] NFO not written: {}", file_path, e);
+    }
+}
+
+// 影片库记录的基本信息，文件大小取整理后的文件
+fn library_entry(media_path: &str) -> LibraryEntry {
+    LibraryEntry {
+        file_size: fs::metadata(media_path)
+            .map(|metadata| metadata.len() as i64)
+            .unwrap_or_default(),
+        scraped_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        ..Default::default()
+    }
+}
+
+// media_path 为影片现在的位置，未移动时与 file_path 相同
+fn scraped_entry(
+    movie: &Movie,
+    file_path: &str,
+    media_path: &str,
+    dir: &str,
+    poster_path: &str,
+    fanart_path: &str,
+) -> LibraryEntry {
+    let image_path = |image: &str| {
+        if image.is_empty() {
+            String::new()
         } else {
-            title.as_str()
-        };
-        let new_location_rule = location_rule.replace("$title", title);
-        location_rule = new_location_rule;
+            Path::new(dir).join(image).to_string_lossy().to_string()
+        }
+    };
+    LibraryEntry {
+        number: movie.number.clone(),
+        source: movie.source.clone(),
+        title: movie.title.clone(),
+        actors: movie
+            .actor
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        file_path: file_path.to_string(),
+        media_path: media_path.to_string(),
+        folder: dir.to_string(),
+        poster_path: image_path(poster_path),
+        fanart_path: image_path(fanart_path),
+        status: "success".to_string(),
+        ..library_entry(media_path)
     }
-    location_rule = location_rule.replace("$number", &movie.number);
-    if location_rule.starts_with('/') {
-        location_rule = location_rule[1..].parse().unwrap();
+}
+
+// 记录到影片库索引，失败不影响刮削
+fn record_library(config: &AppConfig, entry: &LibraryEntry) {
+    let result = Library::open(&config.library.file).and_then(|library| library.record(entry));
+    if let Err(e) = result {
+        println!("[-]Fail to record '{}' in library: {}", entry.file_path, e);
     }
-    let mut path = std::path::PathBuf::from(success_folder);
-    path = path.join(location_rule.trim());
+}
+
+// The output folder of a movie, from location_rule
+fn folder_path(naming: &Naming, config: &AppConfig) -> PathBuf {
+    let success_folder = config.common.success_output_folder.as_str();
+    let location = naming.render_path(&config.name_rule.location_rule, &config.sanitize);
+    PathBuf::from(success_folder).join(location)
+}
+
+fn create_folder(naming: &Naming, config: &AppConfig) -> PathBuf {
+    let path = folder_path(naming, config);
     if !path.exists() {
         match fs::create_dir_all(&path) {
             Ok(_) => {}
@@ -257,9 +457,7 @@ fn create_folder(movie: &Movie, config: &AppConfig) -> PathBuf {
 }
 
 fn is_uncensored(number: &str, config: &AppConfig) -> bool {
-    let re_str = r"[\d-]{4,}|\d{6}_\d{2,3}|(cz|gedo|k|n|red-|se)\d{2,4}|heyzo.+|xxx-av-.+|heydouga-.+|x-art\.\d{2}\.\d{2}\.\d{2}";
-    let re = regex::Regex::new(re_str).unwrap();
-    if re.is_match(number) {
+    if UNCENSORED_REGEX.is_match(number) {
         true
     } else {
         let uncensored_prefix_set: Vec<&str> = config
@@ -272,7 +470,7 @@ fn is_uncensored(number: &str, config: &AppConfig) -> bool {
     }
 }
 
-fn image_ext(url: &str) -> String {
+pub fn image_ext(url: &str) -> String {
     let image_extensions = [".jpg", ".jpeg", ".gif", ".png", ".bmp"];
     for x in image_extensions.iter() {
         if url.ends_with(x) {
@@ -282,18 +480,19 @@ fn image_ext(url: &str) -> String {
     ".jpg".to_string()
 }
 
+/// Downloads the small cover as the poster, returns whether it was downloaded.
 pub async fn download_small_cover(
     cover_small_url: &str,
     dir: &str,
     filename: &str,
     config: &AppConfig,
-) {
+) -> bool {
     if cover_small_url.is_empty() {
-        return;
+        return false;
     }
     let full_filepath = Path::new(dir).join(filename);
     if config.common.download_only_missing_images && file_exit_and_not_empty(&full_filepath) {
-        return;
+        return false;
     }
     let ret = download_file_with_filename(cover_small_url, dir, filename, config).await;
     if ret {
@@ -302,26 +501,30 @@ pub async fn download_small_cover(
             full_filepath.file_name().unwrap().to_string_lossy()
         );
     }
+    ret
 }
 
+/// Downloads the cover as the thumb and copies it as the fanart, returns whether the thumb was
+/// downloaded.
 pub async fn download_cover(
     cover_url: &str,
     dir: &str,
     thumb_file_name: &str,
     fanart_file_name: &str,
     config: &AppConfig,
-) {
+) -> bool {
     if cover_url.is_empty() {
-        return;
+        return false;
     }
     let full_thumb_path = PathBuf::from(dir).join(thumb_file_name);
     if config.common.download_only_missing_images && file_exit_and_not_empty(&full_thumb_path) {
-        return;
+        return false;
     }
 
+    let mut downloaded = false;
     for i in 0..config.proxy.retry {
-        let ret = download_file_with_filename(cover_url, dir, thumb_file_name, config).await;
-        if ret {
+        downloaded = download_file_with_filename(cover_url, dir, thumb_file_name, config).await;
+        if downloaded {
             break;
         }
         println!(
@@ -330,8 +533,8 @@ pub async fn download_cover(
             config.proxy.retry
         );
     }
-    if !file_exit_and_not_empty(&full_thumb_path) {
-        return;
+    if !downloaded || !file_exit_and_not_empty(&full_thumb_path) {
+        return false;
     }
     println!(
         "[+]Image Downloaded! {}",
@@ -344,6 +547,7 @@ pub async fn download_cover(
             eprintln!("[-]Failed to copy thumbnail to fanart: {:?}", e);
         }
     };
+    true
 }
 pub async fn download_file_with_filename(
     url: &str,
@@ -374,19 +578,120 @@ pub async fn download_file_with_filename(
     }
     if !is_success {
         println!("[-]Connect Failed! Please check your Proxy or Network!");
+    } else if let Err(e) = normalize_image(&full_path, config) {
+        println!("[-]Image convert failed! {}: {}", filename, e);
     }
 
     is_success
 }
-pub async fn download_extra_fanart(extra_fanart: &[String], dir: &str, config: &AppConfig) {
+/// Downloads the extra fanart in order, keeping at most `extra_fanart.max_count` frames.
+///
+/// Frames smaller than `min_width`/`min_height` are dropped, and with `dedupe` so are frames that
+/// look the same as the cover or a frame already kept. The kept frames are numbered from 1 and
+/// frames left over from an earlier scrape are removed. With `download_only_missing_images` the
+/// frames already saved are kept and only the missing numbers are downloaded.
+pub async fn download_extra_fanart(
+    extra_fanart: &[String],
+    dir: &str,
+    file_stem: &str,
+    thumb_path: &str,
+    config: &AppConfig,
+) {
     let tm_start = std::time::Instant::now();
-    let tasks = extra_fanart
+    let extra_fanart_path = extra_fanart_dir(Path::new(dir), config);
+    let max_count = match config.extra_fanart.max_count {
+        0 => extra_fanart.len(),
+        max_count => max_count.min(extra_fanart.len()),
+    };
+
+    // 网站的剧照变少时，多出的旧剧照总是删除
+    let mut existing = Vec::new();
+    for (n, file) in numbered_extra_fanart_files(Path::new(dir), file_stem, config) {
+        if n as usize > max_count {
+            let _ = fs::remove_file(file);
+        } else {
+            existing.push((n, file));
+        }
+    }
+    let only_missing = config.common.download_only_missing_images;
+    // 保留的旧剧照也参与去重
+    let mut hashes: Vec<u64> = if only_missing {
+        existing
+            .iter()
+            .filter_map(|(_, file)| image::open(file).ok().map(|img| dhash(&img)))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let taken: HashSet<usize> = if only_missing {
+        existing.iter().map(|(n, _)| *n as usize).collect()
+    } else {
+        HashSet::new()
+    };
+    if taken.len() >= max_count {
+        return;
+    }
+    // 第 N 张剧照对应第 N 个链接，已有的跳过
+    let urls: Vec<(usize, &String)> = extra_fanart
         .iter()
         .enumerate()
-        .map(move |(i, url)| extra_fanart_download_one_by_one(url, i, dir, config))
-        .collect::<Vec<_>>();
+        .filter(|(i, _)| !taken.contains(&(i + 1)))
+        .collect();
+    let cover_hash = image::open(Path::new(dir).join(thumb_path))
+        .ok()
+        .map(|img| dhash(&img));
+    let wanted = max_count - taken.len();
+
+    // 分批下载，被去掉的剧照由后面的补上
+    let mut kept: Vec<PathBuf> = Vec::new();
+    let mut next = 0;
+    while kept.len() < wanted && next < urls.len() {
+        let end = (next + wanted - kept.len()).min(urls.len());
+        let tasks = urls[next..end]
+            .iter()
+            .map(|(i, url)| extra_fanart_download_one_by_one(url, *i, &extra_fanart_path, config))
+            .collect::<Vec<_>>();
+        let files = futures::future::join_all(tasks).await;
+        next = end;
+        for file in files.into_iter().flatten() {
+            match check_extra_fanart(&file, cover_hash, &hashes, config) {
+                Some(hash) if kept.len() < wanted => {
+                    kept.push(file);
+                    hashes.push(hash);
+                }
+                _ => {
+                    let _ = fs::remove_file(&file);
+                }
+            }
+        }
+    }
+    if kept.is_empty() {
+        return;
+    }
 
-    futures::future::join_all(tasks).await;
+    if !only_missing {
+        for (_, stale) in existing {
+            let _ = fs::remove_file(stale);
+        }
+    }
+    let prefix = extra_fanart_prefix(file_stem, config);
+    let free_numbers = (1..=max_count).filter(|n| !taken.contains(n));
+    for (file, n) in kept.iter().zip(free_numbers) {
+        let ext = file
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let target = extra_fanart_path.join(format!("{}{}.{}", prefix, n, ext));
+        if let Err(e) = fs::rename(file, &target) {
+            println!("[-]Failed to save {}: {}", target.display(), e);
+            let _ = fs::remove_file(file);
+        }
+    }
+    println!(
+        "[+]Kept {} of {} extrafanart",
+        kept.len() + taken.len(),
+        extra_fanart.len()
+    );
     if config.debug_mode.switch {
         println!(
             "[!]Extrafanart downloaded runtime {:.3}s",
@@ -395,36 +700,75 @@ pub async fn download_extra_fanart(extra_fanart: &[String], dir: &str, config: &
     }
 }
 
+// Downloads to a temporary name, the frame is numbered once it is kept
 async fn extra_fanart_download_one_by_one(
     extra_fanart_url: &str,
     i: usize,
-    dir: &str,
+    extra_fanart_path: &Path,
     config: &AppConfig,
-) {
-    let extra_fanart_path = Path::new(dir).join(&config.extra_fanart.extra_fanart_folder);
-    let download_only_missing_images = config.common.download_only_missing_images;
-    let jpg_filename = format!("extrafanart-{}.jpg", i + 1);
-    let jpg_full_path = extra_fanart_path.join(&jpg_filename);
+) -> Option<PathBuf> {
+    let filename = format!(
+        ".download-{}{}",
+        i + 1,
+        artwork_ext(extra_fanart_url, config)
+    );
+    let full_path = extra_fanart_path.join(&filename);
 
     for i in 0..config.proxy.retry {
-        if download_only_missing_images && file_exit_and_not_empty(&jpg_full_path) {
-            break;
-        }
         download_file_with_filename(
             extra_fanart_url,
             extra_fanart_path.to_string_lossy().as_ref(),
-            &jpg_filename,
+            &filename,
             config,
         )
         .await;
-        if !file_exit_and_not_empty(&jpg_full_path) {
+        if file_exit_and_not_empty(&full_path) {
+            return Some(full_path);
+        }
+        println!(
+            "[!]Image Download Failed! Trying again. [{}/{}]",
+            i + 1,
+            config.proxy.retry
+        );
+    }
+    None
+}
+
+// The dhash of a frame worth keeping, None when it is too small or a duplicate of the cover or
+// a kept frame
+fn check_extra_fanart(
+    file: &Path,
+    cover_hash: Option<u64>,
+    kept: &[u64],
+    config: &AppConfig,
+) -> Option<u64> {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let img = image::open(file).ok()?;
+    if img.width() < config.extra_fanart.min_width || img.height() < config.extra_fanart.min_height
+    {
+        if config.debug_mode.switch {
             println!(
-                "[!]Image Download Failed! Trying again. [{}/{}]",
-                i + 1,
-                config.proxy.retry
+                "[!]Drop extrafanart {}: {}x{} is too small",
+                name,
+                img.width(),
+                img.height()
             );
         }
+        return None;
+    }
+    let hash = dhash(&img);
+    if config.extra_fanart.dedupe
+        && cover_hash
+            .iter()
+            .chain(kept.iter())
+            .any(|other| similar(hash, *other))
+    {
+        if config.debug_mode.switch {
+            println!("[!]Drop extrafanart {}: duplicate", name);
+        }
+        return None;
     }
+    Some(hash)
 }
 
 pub async fn download_actor_photo(
@@ -436,6 +780,12 @@ pub async fn download_actor_photo(
     if actors.is_empty() || dir.is_empty() {
         return;
     }
+    if let Some(mut library) = ActorPhotoLibrary::open(config) {
+        if let Err(e) = library.download(actors, number, config).await {
+            println!("[-]Fail to update actor photo library: {}", e);
+        }
+        return;
+    }
     let save_path = Path::new(dir);
     if !save_path.is_dir() {
         return;
@@ -445,7 +795,7 @@ pub async fn download_actor_photo(
     let mut dn_list = Vec::new();
     for (actor_name, url) in actors.iter() {
         if url.is_empty().not() {
-            let pic_full_path = actors_dir.join(format!("{}{}", actor_name, image_ext(url)));
+            let pic_full_path = actors_dir.join(actor_photo_name(actor_name, url, config));
             if download_only_missing_images && file_exit_and_not_empty(&pic_full_path) {
                 continue;
             }
@@ -457,9 +807,14 @@ pub async fn download_actor_photo(
     }
     let result = parallel_download_files(dn_list).await;
     let mut failed = 0;
-    for (_i, r) in result.iter().enumerate() {
-        if r.is_err() {
-            failed += 1;
+    for r in result.iter() {
+        match r {
+            Ok(path) => {
+                if let Err(e) = normalize_image(path, config) {
+                    println!("[-]Image convert failed! {}: {}", path.display(), e);
+                }
+            }
+            Err(_) => failed += 1,
         }
     }
     if failed > 0 {
@@ -475,29 +830,31 @@ pub async fn download_actor_photo(
     }
 }
 
+// 演员名中可能含有 / : 等字符，不能直接作为文件名
+pub fn actor_photo_name(actor_name: &str, url: &str, config: &AppConfig) -> String {
+    let ext = artwork_ext(url, config);
+    let name = sanitize_component(actor_name, ext.len(), &config.sanitize);
+    format!("{}{}", name, ext)
+}
+
+/// Moves or links the movie into the output folder, returns where the movie is afterwards.
 pub async fn paste_file_to_folder(
     filepath: &str,
     dir: &str,
-    number: &str,
-    leak_word: &str,
-    c_word: &str,
-    hack_word: &str,
+    file_stem: &str,
     config: &AppConfig,
-) -> Result<(), Box<dyn Error>> {
+) -> Result<String, Box<dyn Error>> {
     let file_path = Path::new(filepath);
     let file_extension = file_path
         .extension()
         .and_then(|ext| ext.to_str())
         .unwrap_or("");
-    let target_path = Path::new(dir).join(format!(
-        "{}{}{}{}{}{}",
-        number, leak_word, c_word, hack_word, ".", file_extension
-    ));
+    let target_path = Path::new(dir).join(format!("{}.{}", file_stem, file_extension));
     // 任何情况下都不要覆盖，以免遭遇数据源或者引擎错误导致所有文件得到同一个number，逐一
     // 同名覆盖致使全部文件损失且不可追回的最坏情况
     if target_path.exists() {
         println!("[-]File Exists on destination path, we will never overwriting.");
-        return Ok(());
+        return Ok(filepath.to_string());
     }
     let link_mode = config.common.link_mode;
     // 如果link_mode 1: 建立软链接 2: 硬链接优先、无法建立硬链接再尝试软链接。
@@ -514,8 +871,10 @@ pub async fn paste_file_to_folder(
     if link_mode == 1 || soft_link {
         let file_rel_path = file_path.strip_prefix(dir).ok().and_then(|p| p.to_str());
         if file_rel_path.is_some() {
-            let symlink_result =
-                util::files::create_soft_link(Path::new(file_rel_path.unwrap()), &target_path_clone);
+            let symlink_result = util::files::create_soft_link(
+                Path::new(file_rel_path.unwrap()),
+                &target_path_clone,
+            );
             if symlink_result.is_err() {
                 util::files::create_soft_link(file_path, &target_path_clone).unwrap();
             }
@@ -523,164 +882,10 @@ pub async fn paste_file_to_folder(
     } else {
         fs::rename(filepath, &target_path_clone)?;
     }
-    Ok(())
-}
-
-pub fn cut_image(config: &AppConfig, dir: &str, thumb_path: &str, poster_path: &str) {
-    let full_path_thumb = Path::new(dir).join(thumb_path);
-    let full_path_poster = Path::new(dir).join(poster_path);
-    if config.common.download_only_missing_images && util::files::file_exit_and_not_empty(&full_path_poster) {
-        return;
-    }
-
-    let img_result = open(&full_path_thumb);
-    let filename = full_path_thumb.file_name().unwrap().to_str().unwrap();
-    if let Ok(mut img) = img_result {
-        let (width, height) = (img.width(), img.height());
-        let poster_image = if (width as f64 / height as f64) > (2.0 / 3.0) {
-            // 如果宽度大于2
-            let s = face_crop_width(&img, filename, width, height, config);
-            img.crop(s.0, s.1, s.2, s.3)
-        } else if (width as f64 / height as f64) < (2.0 / 3.0) {
-            let s = face_crop_height(&img, filename, width, height, config);
-            img.crop(s.0, s.1, s.2, s.3)
-        } else {
-            img
-        };
-        if let Err(e) = poster_image.save(&full_path_poster) {
-            eprintln!("[-]Cover cut failed! {:?}", e);
-        } else {
-            println!(
-                "[+]Image Cutted! {}",
-                full_path_poster.file_name().unwrap().to_string_lossy()
-            );
-        }
-    } else if let Err(e) = img_result {
-        eprintln!("[-]Image open failed! {:?}", e);
-    }
-}
-
-fn face_crop_width(
-    image: &DynamicImage,
-    filename: &str,
-    width: u32,
-    height: u32,
-    config: &AppConfig,
-) -> (u32, u32, u32, u32) {
-    let aspect_ratio = config.face.aspect_ratio;
-    // 新宽度是高度的2/3
-    let crop_width_half = height / 3;
-    let locations_model = config.face.locations_model.clone();
-    let locations_model = locations_model
-        .split(',')
-        .filter(|x| !x.is_empty())
-        .to_owned();
-
-    for model in locations_model {
-        if let Some((center, _top)) = face_center(image, filename, model) {
-            if center < crop_width_half {
-                return (0, 0, crop_width_half * aspect_ratio as u32, height);
-            }
-            let crop_left = center - crop_width_half;
-            let crop_right = center + crop_width_half;
-            if crop_right > width {
-                return (
-                    width - crop_width_half * aspect_ratio as u32,
-                    0,
-                    width,
-                    height,
-                );
-            }
-            return (crop_left, 0, crop_right, height);
-        }
-    }
-
-    println!("[-]Not found face!   {}", filename);
-    // 默认靠右切
-    (
-        width - crop_width_half * aspect_ratio as u32,
-        0,
-        width,
-        height,
-    )
-}
-
-fn face_crop_height(
-    image: &DynamicImage,
-    filename: &str,
-    width: u32,
-    height: u32,
-    config: &AppConfig,
-) -> (u32, u32, u32, u32) {
-    let crop_height = (width as f32 * 3.0 / 2.0).round() as u32;
-
-    let locations_model = config.face.locations_model.clone();
-    let locations_model: Vec<&str> = locations_model
-        .split(',')
-        .filter(|x| !x.is_empty())
-        .collect();
-
-    for model in locations_model {
-        if let Some((_center, top)) = face_center(image, filename, model) {
-            // 如果找到就跳出循环
-            if top > 0 {
-                // 头部靠上
-                let crop_top = top;
-                let crop_bottom = crop_height + top;
-
-                if crop_bottom > height {
-                    return (0, 0, width, crop_height);
-                }
-                return (0, crop_top, width, crop_bottom);
-            }
-        }
-    }
-
-    println!("[-]Not found face!   {}", filename);
-    // 默认从顶部向下切割
-    (0, 0, width, crop_height)
-}
-
-fn face_center(image: &DynamicImage, filename: &str, model: &str) -> Option<(u32, u32)> {
-    let rgb_image = image.to_rgb8();
-    match model {
-        "hog" => {
-            println!("[-]Model {} found face {}", model, filename);
-            let matrix = ImageMatrix::from_image(&rgb_image);
-            let detector = FaceDetector::default();
-            let locations = detector.face_locations(&matrix);
-
-            // Get the center of each detected face
-            get_face_center(locations)
-        }
-        "cnn" => {
-            println!("[-]Model {} found face {}", model, filename);
-            let matrix = ImageMatrix::from_image(&rgb_image);
-            let detector = FaceDetectorCnn::default().unwrap();
-            let locations = detector.face_locations(&matrix);
-
-            // Get the center of each detected face
-            get_face_center(locations)
-        }
-        _ => None,
-    }
-}
-
-fn get_face_center(locations: FaceLocations) -> Option<(u32, u32)> {
-    let face_centers: Vec<(u32, u32)> = locations
-        .iter()
-        .map(|detection| {
-            let center_x = detection.left + (detection.right - detection.left) / 2;
-            let center_y = detection.top + (detection.top - detection.bottom) / 2;
-            (center_x as u32, center_y as u32)
-        })
-        .collect();
-
-    if face_centers.is_empty() {
-        None
+    if target_path_clone.symlink_metadata().is_ok() {
+        Ok(target_path_clone.to_string_lossy().to_string())
     } else {
-        let face_center: (u32, u32) = face_centers.first().unwrap().to_owned();
-        Some(face_center)
+        Ok(filepath.to_string())
     }
 }
 
@@ -752,212 +957,17 @@ pub fn move_subtitles(
     Ok(is_success)
 }
 
-async fn write_nfo_file(
-    config: &AppConfig,
-    movie: &Movie,
-    dir: &str,
-    leak_word: &str,
-    c_word: &str,
-    hack_word: &str,
-    _4k: bool,
-    uncensored: bool,
-    filepath: &str,
-    thumb_path: &str,
-    poster_path: &str,
-    fanart_path: &str,
-) -> Result<(), Box<dyn Error>> {
-    let nfo_path = if config.common.link_mode == 3 {
-        Path::new(&filepath).with_extension("nfo")
-    } else {
-        Path::new(&dir).join(format!(
-            "{}{}{}{}.nfo",
-            movie.number, leak_word, c_word, hack_word
-        ))
-    };
-    let _path = Path::new(dir);
-    fs::create_dir_all(_path).unwrap();
-
-    let title = config
-        .name_rule
-        .naming_rule
-        .replace("$number", &movie.number)
-        .replace("$title", &movie.title.replace(&movie.number, ""));
-    let actor = movie
-        .actor
-        .iter()
-        .map(|(name, thumb)| {
-            let thumb = if thumb.is_empty().not() {
-                format!(".actors/{}{}", name, image_ext(thumb))
-            } else {
-                thumb.to_owned()
-            };
-            Actor {
-                name: name.to_string(),
-                thumb,
-            }
-        })
-        .collect();
-
-    let mut tag: Vec<Tag> = movie.get_tags();
-    if tag.is_empty() {
-        if c_word.is_empty().not() {
-            tag.push(Tag {
-                content: "中文字幕".to_string(),
-            })
-        }
-        if leak_word.is_empty().not() {
-            tag.push(Tag {
-                content: "流出".to_string(),
-            })
-        }
-        if hack_word.is_empty().not() {
-            tag.push(Tag {
-                content: "破解".to_string(),
-            })
-        }
-        if uncensored {
-            tag.push(Tag {
-                content: "无码".to_string(),
-            })
-        }
-    }
-    let thumb = Thumb {
-        content: fanart_path.to_string(),
-    };
-    let fanart = Fanart { thumb: vec![thumb] };
-    let rating = Rating {
-        value: movie.user_rating.to_string(),
-        votes: movie.user_votes.to_string(),
-        max: movie.max_user_rating.to_string(),
-        default: true,
-    };
-    let ratings = Ratings {
-        rating: vec![rating],
-    };
-    let nfo = MovieNFO {
-        title: title.clone(),
-        original_title: movie.title.clone(),
-        sort_title: title,
-        customrating: "JP-18+".to_string(),
-        mpaa: "JP-18+".to_string(),
-        set: movie.series.clone(),
-        studio: movie.studio.clone(),
-        year: movie.year.clone(),
-        outline: movie.outline.clone(),
-        plot: movie.outline.clone(),
-        runtime: movie.runtime.clone(),
-        director: movie.director.clone(),
-        poster: poster_path.to_string(),
-        thumb: thumb_path.to_string(),
-        fanart,
-        actors: actor,
-        maker: movie.studio.clone(),
-        label: movie.label.clone(),
-        tag,
-        num: movie.number.clone(),
-        premiered: movie.release.clone(),
-        release_date: movie.release.clone(),
-        release: movie.release.clone(),
-        userrating: movie.user_rating.clone(),
-        ratings,
-        cover: movie.cover.clone(),
-        trailer: "".to_string(),
-        website: movie.website.clone(),
-    };
-
-    // write movie to nfo file
-    let xml = to_string(&nfo).unwrap();
-    let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>".to_string() + &xml;
-    let doc = Document::from_str(&xml).unwrap();
-    let dp = display::Config {
-        is_pretty: true,
-        indent: 2,
-        end_pad: 0,
-        max_line_length: usize::MAX,
-        entity_mode: display::EntityMode::Standard,
-        indent_text_nodes: false,
-    };
-    let xml = doc.to_string_pretty_with_config(&dp);
-    let mut file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open(&nfo_path)?;
-    // Write the XML string to the file
-    file.write_all(xml.as_bytes())?;
-    println!("[+]Wrote!  {}", &nfo_path.to_string_lossy());
-    Ok(())
-}
-
-#[derive(Serialize)]
-#[serde(rename = "movie")]
-struct MovieNFO {
-    title: String,
-    #[serde(rename = "originaltitle")]
-    original_title: String,
-    #[serde(rename = "sorttitle")]
-    sort_title: String,
-    customrating: String,
-    mpaa: String,
-    set: String,
-    studio: String,
-    year: String,
-    outline: String,
-    plot: String,
-    runtime: String,
-    director: String,
-    poster: String,
-    thumb: String,
-    fanart: Fanart,
-    #[serde(rename = "actor")]
-    actors: Vec<Actor>,
-    maker: String,
-    label: String,
-    #[serde(rename = "tag")]
-    tag: Vec<Tag>,
-    num: String,
-    premiered: String,
-    #[serde(rename = "releasedate")]
-    release_date: String,
-    release: String,
-    userrating: String,
-    ratings: Ratings,
-    cover: String,
-    trailer: String,
-    website: String,
-}
-
-#[derive(Serialize)]
-struct Thumb {
-    #[serde(rename = "$value")]
-    content: String,
-}
-
-#[derive(Serialize)]
-struct Fanart {
-    #[serde(rename = "thumb")]
-    thumb: Vec<Thumb>,
-}
-
-#[derive(Serialize)]
-struct Ratings {
-    #[serde(rename = "rating")]
-    rating: Vec<Rating>,
-}
-
-#[derive(Serialize)]
-pub struct Rating {
-    pub value: String,
-    pub votes: String,
-    #[serde(rename = "@max")]
-    pub max: String,
-    #[serde(rename = "@default")]
-    pub default: bool,
-}
+/// Like `scraping_data_and_move_movie` with a number given by the user.
+///
+/// # Arguments
+///
+/// *  `scraped`  - the movie when it was already scraped, such as the match accepted in interactive
+///    mode, so it is not scraped again.
 pub async fn scraping_data_and_move_movie_with_custom_number(
     file_path: &str,
     custom_number: &str,
     number_extractor: &NumberExtractor,
+    scraped: Option<Movie>,
     specified_source: Option<String>,
     config: &AppConfig,
 ) -> Result<(), Box<dyn Error>> {
@@ -972,6 +982,7 @@ pub async fn scraping_data_and_move_movie_with_custom_number(
             file_path,
             number_extractor,
             custom_number,
+            scraped,
             None,
             specified_source,
             config,
@@ -1048,7 +1059,10 @@ pub fn movie_lists(config: &AppConfig, folder_path: &Path) -> Vec<String> {
     total_movies
 }
 
-pub fn move_failed_folder(filepath: &str, config: &AppConfig) {
+/// Moves a movie that could not be scraped to the failed folder, or adds it to the failed list.
+///
+/// Returns where the movie is afterwards.
+pub fn move_failed_folder(filepath: &str, config: &AppConfig) -> String {
     let failed_folder = config.common.failed_output_folder.as_str();
     let link_mode = config.common.link_mode;
 
@@ -1090,12 +1104,13 @@ pub fn move_failed_folder(filepath: &str, config: &AppConfig) {
             eprintln!("-Failed to open 'where_was_i_before_being_moved.txt'.");
         }
         match fs::rename(filepath, &failed_name) {
-            Ok(_) => {}
+            Ok(_) => return failed_name.to_string_lossy().to_string(),
             Err(e) => {
                 eprintln!("-File Moving to FailedFolder unsuccessful: {}", e);
             }
         }
     }
+    filepath.to_string()
 }
 
 /// Scraping data and move movie function.
@@ -1135,15 +1150,22 @@ pub async fn scraping_data_and_move_movie(
         n_number, movie_path
     );
     if n_number.is_empty().not() {
-        core_main(
+        // 单个影片出错时移到失败文件夹，不中断整个批次
+        if let Err(err) = core_main(
             movie_path,
             &number_extractor,
             n_number.as_str(),
             None,
             None,
+            None,
             config,
         )
-        .await?;
+        .await
+        {
+            eprintln!("[-] [{}] ERROR:", movie_path);
+            eprintln!("[-] {}", err);
+            move_failed_folder(movie_path, config);
+        }
     } else {
         println!("[-] number empty error");
         move_failed_folder(movie_path, config);
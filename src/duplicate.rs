@@ -0,0 +1,318 @@
+use std::error::Error;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+use sha2::{Digest, Sha256};
+
+use crate::config::AppConfig;
+use crate::library::Library;
+use crate::naming::Naming;
+use crate::probe::{probe, MediaInfo};
+
+// Bytes hashed from the start and the end of a file to rule out different files quickly
+const QUICK_HASH_BYTES: u64 = 1024 * 1024;
+
+// The most `v2`, `v3`... versions tried for one movie
+const MAX_VERSIONS: u32 = 99;
+
+/// What to do with a new movie after the duplicate check.
+pub enum Duplicate {
+    /// Process the movie, its media file gets this stem.
+    Keep(String),
+    /// Leave the movie unprocessed, its file is now at this path.
+    Skip(PathBuf),
+}
+
+/// Decides what to do with a movie whose number is already in the output folder.
+///
+/// The movie is a duplicate when its target file exists, or when the library index has another
+/// file with the same number. An identical copy, same size and same content, is always moved
+/// to the duplicates folder. Otherwise `duplicate.policy` decides:
+///
+/// *  `skip`  - leave the new file where it is.
+/// *  `move`  - move the new file to the duplicates folder.
+/// *  `keep_larger`  - keep the larger file, the other one goes to the duplicates folder.
+/// *  `keep_higher_resolution`  - keep the file with more lines, then the larger one.
+/// *  `version`  - keep both, naming the new file with the `$version` placeholder.
+///
+/// With `probe.switch`, files with the same number whose durations differ more than
+/// `max_duration_diff` seconds are different cuts and are always kept as versions.
+///
+/// Returns the media file stem to use, or where the new file is now when it should not be
+/// processed.
+///
+/// # Arguments
+///
+/// *  `media_info`  - what ffprobe found in the new file, when `probe.switch` is on.
+pub fn handle_duplicate(
+    file_path: &str,
+    dir: &Path,
+    number: &str,
+    media_info: Option<&MediaInfo>,
+    naming: &mut Naming,
+    config: &AppConfig,
+) -> Result<Duplicate, Box<dyn Error>> {
+    let file_stem = naming.file_stem(config);
+    let existing = match find_existing(file_path, dir, &file_stem, number, config) {
+        Some(existing) => existing,
+        None => return Ok(Duplicate::Keep(file_stem)),
+    };
+    let new_file = Path::new(file_path);
+    // 未移动时新文件仍在原处
+    let skip = |moved: Option<PathBuf>| Duplicate::Skip(moved.unwrap_or(new_file.to_path_buf()));
+    println!(
+        "[!]Number [{}] already exists at '{}'",
+        number,
+        existing.display()
+    );
+
+    if is_identical(new_file, &existing) {
+        println!("[!]'{}' is an identical copy", file_path);
+        return Ok(skip(move_to_duplicates(new_file, &existing, config)?));
+    }
+
+    let existing_info = if config.probe.switch {
+        probe(&existing, &config.probe.ffprobe)
+    } else {
+        None
+    };
+    let duration = |info: Option<&MediaInfo>| info.and_then(|info| info.duration);
+    let policy = match (duration(media_info), duration(existing_info.as_ref())) {
+        (Some(a), Some(b)) if (a - b).abs() > config.duplicate.max_duration_diff => {
+            println!(
+                "[!]Durations differ ({:.0}s and {:.0}s), keeping both as versions",
+                a, b
+            );
+            "version"
+        }
+        _ => config.duplicate.policy.as_str(),
+    };
+
+    match policy {
+        "move" => Ok(skip(move_to_duplicates(new_file, &existing, config)?)),
+        "keep_larger" | "keep_higher_resolution" => {
+            let new_is_better = if policy == "keep_higher_resolution" {
+                let new_height = video_height(new_file, media_info, naming.get("resolution"));
+                let existing_height = video_height(&existing, existing_info.as_ref(), "");
+                new_height > existing_height
+                    || (new_height == existing_height && file_size(new_file) > file_size(&existing))
+            } else {
+                file_size(new_file) > file_size(&existing)
+            };
+            if new_is_better {
+                // link_mode 下旧文件留在原处，新文件无法放入
+                if move_to_duplicates(&existing, new_file, config)?.is_none() {
+                    return Ok(skip(None));
+                }
+                println!("[+]Keep the new file, replacing '{}'", existing.display());
+                Ok(Duplicate::Keep(file_stem))
+            } else {
+                Ok(skip(move_to_duplicates(new_file, &existing, config)?))
+            }
+        }
+        "version" => Ok(Duplicate::Keep(version_file_stem(
+            new_file, dir, &file_stem, naming, config,
+        )?)),
+        _ => {
+            println!(
+                "[-]Duplicate policy is skip, '{}' is left in place.",
+                file_path
+            );
+            Ok(skip(None))
+        }
+    }
+}
+
+// The existing file of the same movie: the target file, or a file with the same number in the
+// library
+fn find_existing(
+    file_path: &str,
+    dir: &Path,
+    file_stem: &str,
+    number: &str,
+    config: &AppConfig,
+) -> Option<PathBuf> {
+    let new_file = Path::new(file_path);
+    let is_other = |path: &Path| path.is_file() && !same_file(path, new_file);
+
+    let target = dir.join(format!("{}{}", file_stem, extension(new_file)));
+    if is_other(&target) {
+        return Some(target);
+    }
+    if config.library.switch {
+        let library = Library::open(&config.library.file).ok()?;
+        return library
+            .with_number(number)
+            .ok()?
+            .into_iter()
+            .map(|entry| PathBuf::from(entry.media_path))
+            .find(|path| is_other(path));
+    }
+    None
+}
+
+// Picks a `$version` for the new file, its resolution or v2, v3... when that is taken
+fn version_file_stem(
+    new_file: &Path,
+    dir: &Path,
+    file_stem: &str,
+    naming: &mut Naming,
+    config: &AppConfig,
+) -> Result<String, Box<dyn Error>> {
+    let ext = extension(new_file);
+    let resolution = naming.get("resolution").to_string();
+    let candidates = std::iter::once(resolution)
+        .filter(|resolution| !resolution.is_empty())
+        .chain((2..=MAX_VERSIONS).map(|i| format!("v{}", i)));
+    for version in candidates {
+        // media_rule 中没有 $version 时 file_stem 把版本追加在文件名后
+        naming.set("version", &version);
+        let stem = naming.file_stem(config);
+        if !dir.join(format!("{}{}", stem, ext)).exists() {
+            println!("[+]Keep both, the new file is version '{}'", version);
+            return Ok(stem);
+        }
+    }
+    Err(format!(
+        "[-]Versions v2 to v{} of '{}' are all taken",
+        MAX_VERSIONS, file_stem
+    )
+    .into())
+}
+
+/// Whether two files have the same size and the same content.
+///
+/// The first and last megabyte are compared first, the whole files are hashed only when those
+/// match.
+pub fn is_identical(a: &Path, b: &Path) -> bool {
+    let size = file_size(a);
+    if size == 0 || size != file_size(b) {
+        return false;
+    }
+    match (quick_hash(a), quick_hash(b)) {
+        (Ok(a), Ok(b)) if a == b => {}
+        _ => return false,
+    }
+    match (full_hash(a), full_hash(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// sha256 of the first and the last megabyte, tells most different files apart without
+// reading whole movies
+fn quick_hash(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; QUICK_HASH_BYTES.min(size) as usize];
+    file.read_exact(&mut buffer)?;
+    hasher.update(&buffer);
+    if size > QUICK_HASH_BYTES {
+        file.seek(SeekFrom::Start(size - buffer.len() as u64))?;
+        file.read_exact(&mut buffer)?;
+        hasher.update(&buffer);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+// sha256 of the whole file
+fn full_hash(path: &Path) -> Result<String, Box<dyn Error>> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or_default()
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default()
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+// The video height from ffprobe, or from a resolution such as 1080p or 4K in the file name
+fn video_height(path: &Path, info: Option<&MediaInfo>, resolution: &str) -> u32 {
+    if let Some(info) = info.filter(|info| info.height > 0) {
+        return info.height;
+    }
+    let resolution = if resolution.is_empty() {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    } else {
+        resolution.to_lowercase()
+    };
+    [
+        ("4320p", 4320),
+        ("8k", 4320),
+        ("2160p", 2160),
+        ("4k", 2160),
+        ("1440p", 1440),
+        ("1080p", 1080),
+        ("720p", 720),
+        ("576p", 576),
+        ("480p", 480),
+    ]
+    .iter()
+    .find(|(name, _)| resolution.contains(name))
+    .map(|(_, height)| *height)
+    .unwrap_or_default()
+}
+
+// Moves a file to the duplicates folder and records where it came from, returns where it was
+// moved to. With link_mode the source files are only linked, so they are left in place.
+fn move_to_duplicates(
+    file: &Path,
+    kept: &Path,
+    config: &AppConfig,
+) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if config.common.link_mode > 0 {
+        println!(
+            "[-]Duplicate '{}' is left in place because of link_mode.",
+            file.display()
+        );
+        return Ok(None);
+    }
+    let folder = PathBuf::from(&config.duplicate.folder);
+    fs::create_dir_all(&folder)?;
+    let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+    let mut target = folder.join(file_name.as_ref());
+    let mut i = 1;
+    while target.exists() {
+        target = folder.join(format!("{}.{}", i, file_name));
+        i += 1;
+    }
+    if fs::rename(file, &target).is_err() {
+        // 跨盘时无法重命名，复制后删除
+        fs::copy(file, &target)?;
+        fs::remove_file(file)?;
+    }
+    println!("[+]Moved duplicate to '{}'", target.display());
+
+    let log = folder.join("duplicates.txt");
+    let mut log = OpenOptions::new().append(true).create(true).open(log)?;
+    writeln!(
+        log,
+        "{} FROM[{}]TO[{}]KEPT[{}]",
+        Local::now().format("%Y-%m-%d %H:%M"),
+        file.display(),
+        target.display(),
+        kept.display()
+    )?;
+    Ok(Some(target))
+}
@@ -0,0 +1,144 @@
+use std::cell::RefCell;
+
+use dlib_face_recognition::{FaceDetector, FaceDetectorCnn, FaceDetectorTrait, ImageMatrix};
+use image::DynamicImage;
+
+use crate::config::AppConfig;
+
+thread_local! {
+    // Loading a detector, the CNN model above all, costs far more than detecting faces,
+    // so each thread loads them once and keeps them for the rest of the process.
+    static DETECTORS: RefCell<Detectors> = RefCell::new(Detectors::default());
+}
+
+#[derive(Default)]
+struct Detectors {
+    hog: Option<FaceDetector>,
+    // None until loaded, Err when the model is not available
+    cnn: Option<Result<FaceDetectorCnn, String>>,
+}
+
+/// A detected face in image coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaceBox {
+    pub left: i64,
+    pub top: i64,
+    pub right: i64,
+    pub bottom: i64,
+}
+
+impl FaceBox {
+    pub fn area(&self) -> i64 {
+        (self.right - self.left).max(0) * (self.bottom - self.top).max(0)
+    }
+
+    pub fn center(&self) -> (u32, u32) {
+        let center_x = self.left + (self.right - self.left) / 2;
+        let center_y = self.top + (self.bottom - self.top) / 2;
+        (center_x.max(0) as u32, center_y.max(0) as u32)
+    }
+
+    // The box around both faces
+    fn union(&self, other: &FaceBox) -> FaceBox {
+        FaceBox {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+}
+
+/// Detects faces with one model, `hog` or `cnn`. A CNN model that can not be loaded falls back to
+/// HOG.
+pub fn detect_faces(image: &DynamicImage, model: &str, config: &AppConfig) -> Vec<FaceBox> {
+    let rgb_image = image.to_rgb8();
+    let matrix = ImageMatrix::from_image(&rgb_image);
+    DETECTORS.with(|detectors| {
+        let mut detectors = detectors.borrow_mut();
+        let locations = match model {
+            "cnn" => {
+                let cnn = detectors.cnn.get_or_insert_with(|| {
+                    let result = match &config.face.cnn_model {
+                        Some(path) => FaceDetectorCnn::open(path),
+                        None => FaceDetectorCnn::default(),
+                    };
+                    result.map_err(|e| {
+                        println!("[-]CNN face model unavailable, fall back to HOG: {:?}", e);
+                        format!("{:?}", e)
+                    })
+                });
+                match cnn {
+                    Ok(cnn) => cnn.face_locations(&matrix),
+                    Err(_) => detectors
+                        .hog
+                        .get_or_insert_with(FaceDetector::default)
+                        .face_locations(&matrix),
+                }
+            }
+            "hog" => detectors
+                .hog
+                .get_or_insert_with(FaceDetector::default)
+                .face_locations(&matrix),
+            _ => return Vec::new(),
+        };
+        locations
+            .iter()
+            .map(|rect| FaceBox {
+                left: rect.left,
+                top: rect.top,
+                right: rect.right,
+                bottom: rect.bottom,
+            })
+            .collect()
+    })
+}
+
+/// The point a poster should be centered on, from the faces found by the `face.locations_model`
+/// detectors, tried in order.
+///
+/// `face.selection` picks the face: `largest`, `central` (closest to the image center) or `all`
+/// (the center of the box around every face).
+pub fn face_focus(image: &DynamicImage, filename: &str, config: &AppConfig) -> Option<(u32, u32)> {
+    let models = config.face.locations_model.split(',').map(|m| m.trim());
+    for model in models.filter(|m| !m.is_empty()) {
+        let faces = detect_faces(image, model, config);
+        if faces.is_empty() {
+            continue;
+        }
+        let selection = config.face.selection();
+        let face = select_face(&faces, selection, image.width(), image.height())?;
+        if config.debug_mode.switch {
+            println!(
+                "[+]Model {} found {} face(s) in {}: {:?}, use {} {:?}",
+                model,
+                faces.len(),
+                filename,
+                faces,
+                selection,
+                face
+            );
+        } else {
+            println!("[+]Model {} found face {}", model, filename);
+        }
+        return Some(face.center());
+    }
+    None
+}
+
+fn select_face(faces: &[FaceBox], selection: &str, width: u32, height: u32) -> Option<FaceBox> {
+    match selection {
+        "all" => faces
+            .iter()
+            .copied()
+            .reduce(|bounds, face| bounds.union(&face)),
+        "central" => {
+            let (center_x, center_y) = (width as i64 / 2, height as i64 / 2);
+            faces.iter().copied().min_by_key(|face| {
+                let (x, y) = face.center();
+                (x as i64 - center_x).pow(2) + (y as i64 - center_y).pow(2)
+            })
+        }
+        _ => faces.iter().copied().max_by_key(|face| face.area()),
+    }
+}
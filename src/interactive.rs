@@ -0,0 +1,160 @@
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+
+use crate::config::{AppConfig, NumberExtractor};
+use crate::core::scraping_data_and_move_movie_with_custom_number;
+use crate::number_parser::{get_number, DEFAULT_NUMBER_EXTRACTOR};
+use crate::parser::Movie;
+use crate::scraping::Scraping;
+
+/// The user's verdict on a previewed match.
+pub enum ReviewDecision {
+    Accept {
+        number: String,
+        number_extractor: Box<NumberExtractor>,
+        movie: Box<Movie>,
+    },
+    Skip,
+}
+
+/// Scrapes and moves a movie after the user has confirmed the match.
+///
+/// # Arguments
+///
+/// *  `movie_path`  - The path of the movie.
+/// *  `custom_number`  - A number to start with instead of the extracted one.
+/// *  `specified_source`  - A source to start with instead of the reordered source list.
+/// *  `config`  - The application configuration.
+pub async fn scraping_data_and_move_movie_interactive(
+    movie_path: &str,
+    custom_number: Option<String>,
+    specified_source: Option<String>,
+    config: &AppConfig,
+) -> Result<(), Box<dyn Error>> {
+    let (number, number_extractor) = match custom_number {
+        Some(number) => (number, DEFAULT_NUMBER_EXTRACTOR.to_owned()),
+        None => get_number(config, movie_path)
+            .unwrap_or_else(|| (String::new(), DEFAULT_NUMBER_EXTRACTOR.to_owned())),
+    };
+
+    match review_movie(
+        movie_path,
+        number,
+        number_extractor,
+        specified_source,
+        config,
+    )
+    .await?
+    {
+        ReviewDecision::Accept {
+            number,
+            number_extractor,
+            movie,
+        } => {
+            // 使用预览时的结果，不再重新刮削
+            scraping_data_and_move_movie_with_custom_number(
+                movie_path,
+                &number,
+                &number_extractor,
+                Some(*movie),
+                None,
+                config,
+            )
+            .await
+        }
+        ReviewDecision::Skip => {
+            println!("[!]Skip '{}'", movie_path);
+            println!("[*]======================================================");
+            Ok(())
+        }
+    }
+}
+
+/// Shows what would be scraped for a file and asks the user to accept, correct or skip it.
+pub async fn review_movie(
+    movie_path: &str,
+    number: String,
+    number_extractor: NumberExtractor,
+    specified_source: Option<String>,
+    config: &AppConfig,
+) -> Result<ReviewDecision, Box<dyn Error>> {
+    let mut number = number;
+    let mut number_extractor = number_extractor;
+    let mut source = specified_source;
+
+    loop {
+        let movie = if number.is_empty() {
+            None
+        } else {
+            let mut scraping = Scraping::new(config);
+            scraping
+                .search(&number, &number_extractor, None, source.clone())
+                .await
+        };
+        print_preview(movie_path, &number, &movie);
+
+        let answer = prompt("[?]Accept (a), enter number (n), choose source (s), skip (k): ")?;
+        match answer.to_lowercase().as_str() {
+            "a" | "" => match movie {
+                Some(movie) => {
+                    return Ok(ReviewDecision::Accept {
+                        number,
+                        number_extractor: Box::new(number_extractor),
+                        movie: Box::new(movie),
+                    });
+                }
+                None => println!("[-]Nothing to accept, enter another number or source."),
+            },
+            "n" => {
+                let new_number = prompt("[?]Number: ")?;
+                if new_number.is_empty() {
+                    continue;
+                }
+                number_extractor = config
+                    .number_extractor
+                    .iter()
+                    .find(|extractor| extractor.get_number(&new_number).is_some())
+                    .cloned()
+                    .unwrap_or_else(|| DEFAULT_NUMBER_EXTRACTOR.to_owned());
+                number = new_number;
+            }
+            "s" => {
+                let mut sources: Vec<String> = config.get_sources().keys().cloned().collect();
+                sources.sort();
+                println!("[+]Sources: {}", sources.join(", "));
+                let new_source = prompt("[?]Source (empty for all): ")?;
+                if new_source.is_empty() {
+                    source = None;
+                } else if sources.contains(&new_source) {
+                    source = Some(new_source);
+                } else {
+                    println!("[-]Unknown source '{}'", new_source);
+                }
+            }
+            "k" => return Ok(ReviewDecision::Skip),
+            _ => println!("[-]Unknown choice '{}'", answer),
+        }
+    }
+}
+
+fn print_preview(movie_path: &str, number: &str, movie: &Option<Movie>) {
+    println!("[+]==================== Review =========================");
+    println!("[+]File:   {}", movie_path);
+    println!("[+]Number: {}", number);
+    match movie {
+        Some(movie) => {
+            println!("[+]Source: {}", movie.source);
+            println!("[+]Title:  {}", movie.title);
+            println!("[+]Cover:  {}", movie.cover);
+        }
+        None => println!("[-]No metadata found"),
+    }
+}
+
+fn prompt(message: &str) -> io::Result<String> {
+    print!("{}", message);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
@@ -1,10 +1,26 @@
 pub mod translator;
+pub mod actor_db;
+pub mod actor_photo;
+pub mod artwork;
+pub mod badge;
 pub mod config;
 pub mod core;
+pub mod duplicate;
+#[cfg(feature = "face")]
+pub mod face;
+pub mod interactive;
+pub mod library;
+pub mod naming;
+pub mod nfo;
 pub mod number_parser;
+pub mod output;
 pub mod parser;
+pub mod poster;
+pub mod probe;
 pub mod request;
+pub mod sanitize;
 pub mod scraping;
 pub mod xpath;
 pub mod strings;
+pub mod subtitle;
 pub mod site_search;
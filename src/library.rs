@@ -0,0 +1,174 @@
+use std::error::Error;
+use std::path::Path;
+
+use rusqlite::{params, Connection, Row};
+
+const CREATE_TABLE: &str = "
+CREATE TABLE IF NOT EXISTS movies (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    number      TEXT NOT NULL,
+    source      TEXT NOT NULL,
+    title       TEXT NOT NULL,
+    actors      TEXT NOT NULL,
+    file_path   TEXT NOT NULL,
+    media_path  TEXT NOT NULL UNIQUE,
+    folder      TEXT NOT NULL,
+    nfo_path    TEXT NOT NULL,
+    poster_path TEXT NOT NULL,
+    fanart_path TEXT NOT NULL,
+    file_size   INTEGER NOT NULL,
+    scraped_at  TEXT NOT NULL,
+    status      TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS movies_number ON movies (number);
+";
+
+const COLUMNS: &str = "number, source, title, actors, file_path, media_path, folder, nfo_path, \
+                       poster_path, fanart_path, file_size, scraped_at, status";
+
+/// A movie processed by `core_main`.
+#[derive(Debug, Default, Clone)]
+pub struct LibraryEntry {
+    pub number: String,
+    pub source: String,
+    pub title: String,
+    /// Actor names separated by `, `.
+    pub actors: String,
+    /// Where the movie was found.
+    pub file_path: String,
+    /// Where the movie is now, the same as `file_path` when it was not moved.
+    pub media_path: String,
+    pub folder: String,
+    pub nfo_path: String,
+    pub poster_path: String,
+    pub fanart_path: String,
+    pub file_size: i64,
+    pub scraped_at: String,
+    /// `success`, `moved` (main mode 2), `skipped` (duplicates) or `failed`.
+    pub status: String,
+}
+
+impl LibraryEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<LibraryEntry> {
+        Ok(LibraryEntry {
+            number: row.get(0)?,
+            source: row.get(1)?,
+            title: row.get(2)?,
+            actors: row.get(3)?,
+            file_path: row.get(4)?,
+            media_path: row.get(5)?,
+            folder: row.get(6)?,
+            nfo_path: row.get(7)?,
+            poster_path: row.get(8)?,
+            fanart_path: row.get(9)?,
+            file_size: row.get(10)?,
+            scraped_at: row.get(11)?,
+            status: row.get(12)?,
+        })
+    }
+
+    /// The artwork and NFO files of a scraped movie that are not on disk.
+    pub fn missing_files(&self) -> Vec<&'static str> {
+        [
+            ("nfo", &self.nfo_path),
+            ("poster", &self.poster_path),
+            ("fanart", &self.fanart_path),
+        ]
+        .iter()
+        .filter(|(_, path)| path.is_empty() || !Path::new(path).is_file())
+        .map(|(name, _)| *name)
+        .collect()
+    }
+}
+
+/// A SQLite index of every movie the tool has processed.
+pub struct Library {
+    conn: Connection,
+}
+
+impl Library {
+    pub fn open(file: &str) -> Result<Library, Box<dyn Error>> {
+        if let Some(parent) = Path::new(file).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(file)?;
+        conn.execute_batch(CREATE_TABLE)?;
+        Ok(Library { conn })
+    }
+
+    /// Adds a movie, replacing the entry with the same media path.
+    pub fn record(&self, entry: &LibraryEntry) -> Result<(), Box<dyn Error>> {
+        self.conn.execute(
+            &format!(
+                "INSERT OR REPLACE INTO movies ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                COLUMNS
+            ),
+            params![
+                entry.number,
+                entry.source,
+                entry.title,
+                entry.actors,
+                entry.file_path,
+                entry.media_path,
+                entry.folder,
+                entry.nfo_path,
+                entry.poster_path,
+                entry.fanart_path,
+                entry.file_size,
+                entry.scraped_at,
+                entry.status,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Movies whose number, title or actors contain the text, every movie when it is empty.
+    pub fn search(&self, text: &str) -> Result<Vec<LibraryEntry>, Box<dyn Error>> {
+        let pattern = format!("%{}%", text);
+        self.query(
+            "WHERE number LIKE ?1 OR title LIKE ?1 OR actors LIKE ?1 ORDER BY number",
+            &pattern,
+        )
+    }
+
+    /// Movies kept in the library with this number, failed and skipped duplicates excluded.
+    pub fn with_number(&self, number: &str) -> Result<Vec<LibraryEntry>, Box<dyn Error>> {
+        self.query(
+            "WHERE number = ?1 AND status NOT IN ('failed', 'skipped')",
+            number,
+        )
+    }
+
+    pub fn with_status(&self, status: &str) -> Result<Vec<LibraryEntry>, Box<dyn Error>> {
+        self.query("WHERE status = ?1 ORDER BY number", status)
+    }
+
+    /// Movies sharing a number with another movie in the library, ordered by number.
+    pub fn duplicates(&self) -> Result<Vec<LibraryEntry>, Box<dyn Error>> {
+        self.query(
+            "WHERE status != ?1 AND number IN (
+                SELECT number FROM movies WHERE status != ?1 GROUP BY number HAVING COUNT(*) > 1
+            ) ORDER BY number, file_size DESC",
+            "failed",
+        )
+    }
+
+    /// Scraped movies missing their NFO or artwork, see `LibraryEntry::missing_files`.
+    pub fn missing_artwork(&self) -> Result<Vec<LibraryEntry>, Box<dyn Error>> {
+        Ok(self
+            .with_status("success")?
+            .into_iter()
+            .filter(|entry| !entry.missing_files().is_empty())
+            .collect())
+    }
+
+    fn query(&self, condition: &str, param: &str) -> Result<Vec<LibraryEntry>, Box<dyn Error>> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!("SELECT {} FROM movies {}", COLUMNS, condition))?;
+        let entries = stmt
+            .query_map(params![param], LibraryEntry::from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+}
@@ -9,11 +9,16 @@ use chrono::Local;
 use clap::{arg, Parser, Subcommand};
 use rand::Rng;
 
+use movie_metadata_capture::actor_db::ActorDb;
 use movie_metadata_capture::config::AppConfig;
 use movie_metadata_capture::core::{
     movie_lists, scraping_data_and_move_movie, scraping_data_and_move_movie_with_custom_number,
 };
-use movie_metadata_capture::number_parser::{get_number, DEFAULT_NUMBER_EXTRACTOR};
+use movie_metadata_capture::interactive::scraping_data_and_move_movie_interactive;
+use movie_metadata_capture::library::{Library, LibraryEntry};
+use movie_metadata_capture::number_parser::{
+    get_number, print_explain, run_number_self_test, DEFAULT_NUMBER_EXTRACTOR,
+};
 use movie_metadata_capture::scraping::Scraping;
 
 #[tokio::main]
@@ -50,11 +55,74 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 println!("{:?}", movie);
             }
         }
+        SubCommand::Extract(extract_args) => {
+            if extract_args.self_test {
+                let failed = run_number_self_test(&config);
+                if failed > 0 {
+                    return Err(format!("[-]{} number test case(s) failed", failed).into());
+                }
+            } else if let Some(file) = extract_args.file {
+                print_explain(&config, &[file]);
+            } else {
+                let folder = extract_args
+                    .folder
+                    .unwrap_or_else(|| config.common.source_folder.clone());
+                let movie_list = movie_lists(&config, Path::new(&folder));
+                print_explain(&config, &movie_list);
+            }
+        }
+        SubCommand::Actor(actor_args) => {
+            let mut actor_db = ActorDb::load(&config.actor_db.file)?;
+            if let Some(file) = actor_args.import {
+                let added = actor_db.import(&file)?;
+                actor_db.save(&config.actor_db.file)?;
+                println!(
+                    "[+]Import {} new actors from '{}' into '{}'",
+                    added, file, config.actor_db.file
+                );
+            }
+            if let Some(file) = actor_args.export {
+                actor_db.save(&file)?;
+                println!("[+]Export {} actors to '{}'", actor_db.actors.len(), file);
+            }
+        }
+        SubCommand::Library(library_args) => {
+            let library = Library::open(&config.library.file)?;
+            if library_args.duplicates {
+                let entries = library.duplicates()?;
+                println!("[+]Find {} movies sharing a number.", entries.len());
+                entries.iter().for_each(print_library_entry);
+            } else if library_args.missing {
+                let entries = library.missing_artwork()?;
+                println!("[+]Find {} movies missing files.", entries.len());
+                for entry in entries.iter() {
+                    print_library_entry(entry);
+                    println!("    missing: {}", entry.missing_files().join(", "));
+                }
+            } else if let Some(status) = library_args.status {
+                let entries = library.with_status(&status)?;
+                println!("[+]Find {} movies with status '{}'.", entries.len(), status);
+                entries.iter().for_each(print_library_entry);
+            } else {
+                let entries = library.search(&library_args.search.unwrap_or_default())?;
+                println!("[+]Find {} movies.", entries.len());
+                entries.iter().for_each(print_library_entry);
+            }
+        }
         SubCommand::Scraping(scraping_args) => {
             config.create_failed_folder().await?;
 
             let single_file_path = scraping_args.file.unwrap_or_default();
-            if !single_file_path.is_empty() {
+            if !single_file_path.is_empty() && scraping_args.interactive {
+                println!("[+]==================== Single File =====================");
+                scraping_data_and_move_movie_interactive(
+                    &single_file_path,
+                    scraping_args.custom_number,
+                    scraping_args.source,
+                    &config,
+                )
+                .await?;
+            } else if !single_file_path.is_empty() {
                 println!("[+]==================== Single File =====================");
                 let (custom_number, number_extractor) = if scraping_args.custom_number.is_none() {
                     get_number(&config, single_file_path.as_str()).unwrap()
@@ -68,6 +136,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
                     &single_file_path,
                     &custom_number,
                     &number_extractor,
+                    None,
                     scraping_args.source,
                     &config,
                 )
@@ -84,7 +153,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 println!("[+]Find {} movies.", movie_count);
                 println!("[*]======================================================");
                 for movie_path in movie_list {
-                    scraping_data_and_move_movie(movie_path.as_str(), &config).await?;
+                    if scraping_args.interactive {
+                        scraping_data_and_move_movie_interactive(
+                            movie_path.as_str(),
+                            None,
+                            None,
+                            &config,
+                        )
+                        .await?;
+                    } else {
+                        scraping_data_and_move_movie(movie_path.as_str(), &config).await?;
+                    }
                     let mut rng = rand::thread_rng();
                     let sleep_seconds = rng.gen_range(config.common.sleep..config.common.sleep + 2);
                     thread::sleep(Duration::from_secs(sleep_seconds));
@@ -111,6 +190,20 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn print_library_entry(entry: &LibraryEntry) {
+    println!(
+        "[{}] {} [{}] {} ({}, {} bytes, {})",
+        entry.status,
+        entry.number,
+        entry.source,
+        entry.title,
+        entry.actors,
+        entry.file_size,
+        entry.scraped_at
+    );
+    println!("    {}", entry.media_path);
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Cli {
@@ -125,6 +218,9 @@ pub struct Cli {
 pub enum SubCommand {
     Info(InfoArgs),
     Scraping(ScrapingArgs),
+    Extract(ExtractArgs),
+    Actor(ActorArgs),
+    Library(LibraryArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -144,4 +240,52 @@ pub struct ScrapingArgs {
 
     #[arg(long, required = false)]
     pub source: Option<String>,
+
+    /// Review each match before it is scraped and moved
+    #[arg(long)]
+    pub interactive: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ExtractArgs {
+    #[arg(long, required = false)]
+    pub file: Option<String>,
+
+    /// Explain every movie in this folder, defaults to the source folder
+    #[arg(long, required = false)]
+    pub folder: Option<String>,
+
+    /// Run the `number_test` cases from the config
+    #[arg(long)]
+    pub self_test: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ActorArgs {
+    /// Merge the actors of a JSON file into the actor database
+    #[arg(long, required = false)]
+    pub import: Option<String>,
+
+    /// Write the actor database to a JSON file
+    #[arg(long, required = false)]
+    pub export: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct LibraryArgs {
+    /// List movies whose number, title or actors contain this text
+    #[arg(long, required = false)]
+    pub search: Option<String>,
+
+    /// List movies sharing a number
+    #[arg(long)]
+    pub duplicates: bool,
+
+    /// List scraped movies missing their NFO or artwork
+    #[arg(long)]
+    pub missing: bool,
+
+    /// List movies with this status: success, moved, skipped or failed
+    #[arg(long, required = false)]
+    pub status: Option<String>,
 }
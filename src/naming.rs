@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::{ActorFolder, AppConfig, Sanitize};
+use crate::parser::Movie;
+use crate::sanitize::{sanitize_chars, sanitize_component, sanitize_path, truncate_chars};
+
+// Bytes kept free after the media file stem for suffixes such as `-poster.jpeg` or `.nfo`
+const FILE_SUFFIX_RESERVE: usize = 16;
+
+lazy_static! {
+    static ref PART_REGEX: Regex =
+        Regex::new(r"(?i)[-_ .](?:cd|part|pt|disc|disk)[-_ ]?(\d{1,2})(?:[^a-z0-9]|$)").unwrap();
+    static ref RESOLUTION_REGEX: Regex = Regex::new(
+        r"(?i)(?:^|[^a-z0-9])(4320p|2160p|1440p|1080p|720p|576p|480p|8k|4k)(?:[^a-z0-9]|$)"
+    )
+    .unwrap();
+}
+
+/// Placeholders usable in `location_rule`, `naming_rule` and `media_rule`.
+pub const PLACEHOLDERS: [&str; 13] = [
+    "number",
+    "title",
+    "actor",
+    "first_actor",
+    "studio",
+    "year",
+    "release",
+    "series",
+    "label",
+    "director",
+    "part",
+    "resolution",
+    "version",
+];
+
+/// Names generated for a movie from the naming rules.
+///
+/// A template is plain text with placeholders:
+///
+/// *  `$title`  - the value of a placeholder.
+/// *  `${title:30}`  - the value truncated to 30 characters.
+/// *  `{ - $series}`  - a conditional segment, dropped when any placeholder inside it is empty.
+/// *  `$$`  - a literal `$`.
+pub struct Naming {
+    values: HashMap<&'static str, String>,
+    max_title_len: usize,
+    pub leak_word: &'static str,
+    pub c_word: &'static str,
+    pub hack_word: &'static str,
+}
+
+impl Naming {
+    pub fn new(
+        movie: &Movie,
+        file_path: &str,
+        leak_word: &'static str,
+        c_word: &'static str,
+        hack_word: &'static str,
+        config: &AppConfig,
+    ) -> Self {
+        let actor_names = folder_actors(movie, config);
+        let actor = actor_folder_name(&actor_names, &config.actor_folder);
+        let file_name = Path::new(file_path)
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        let part = PART_REGEX
+            .captures(file_name)
+            .map(|m| m[1].trim_start_matches('0').to_string())
+            .unwrap_or_default();
+        let resolution = RESOLUTION_REGEX
+            .captures(file_name)
+            .map(|m| {
+                let resolution = m[1].to_lowercase();
+                if resolution.ends_with('k') {
+                    resolution.to_uppercase()
+                } else {
+                    resolution
+                }
+            })
+            .unwrap_or_default();
+        // 标题中常带有番号，去掉以免重复
+        let title = movie.title.replace(&movie.number, "").trim().to_string();
+
+        let mut values = HashMap::new();
+        values.insert("number", movie.number.clone());
+        values.insert("title", title);
+        values.insert("actor", actor);
+        values.insert(
+            "first_actor",
+            actor_names.first().cloned().unwrap_or_default(),
+        );
+        values.insert("studio", movie.studio.clone());
+        values.insert("year", movie.year.clone());
+        values.insert("release", movie.release.clone());
+        values.insert("series", movie.series.clone());
+        values.insert("label", movie.label.clone());
+        values.insert("director", movie.director.clone());
+        values.insert("part", part);
+        values.insert("resolution", resolution);
+        // 仅在保留重复影片的多个版本时设置
+        values.insert("version", String::new());
+
+        Naming {
+            values,
+            max_title_len: config.name_rule.max_title_len,
+            leak_word,
+            c_word,
+            hack_word,
+        }
+    }
+
+    /// Overrides the value of a placeholder.
+    pub fn set(&mut self, placeholder: &'static str, value: &str) {
+        self.values.insert(placeholder, value.to_string());
+    }
+
+    pub fn get(&self, placeholder: &str) -> &str {
+        self.values
+            .get(placeholder)
+            .map(|value| value.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Renders a template with the values of this movie.
+    ///
+    /// Values are only truncated where the template asks for it, as in `${title:30}`.
+    pub fn render(&self, template: &str) -> String {
+        let chars: Vec<char> = template.chars().collect();
+        let mut pos = 0;
+        self.render_segment(&chars, &mut pos, false, None).0
+    }
+
+    /// Renders a template into a relative path.
+    ///
+    /// Values are sanitized before they are inserted, so a `/` in a title never creates a folder.
+    pub fn render_path(&self, template: &str, sanitize: &Sanitize) -> PathBuf {
+        let chars: Vec<char> = template.chars().collect();
+        let mut pos = 0;
+        let path = self
+            .render_segment(&chars, &mut pos, false, Some(sanitize))
+            .0;
+        sanitize_path(&path, sanitize)
+    }
+
+    /// The media file name without extension, shared by the NFO and images named after the movie.
+    ///
+    /// A `$version` that `media_rule` does not use is appended as ` - <version>`, so every version
+    /// of a movie gets its own NFO and images.
+    pub fn file_stem(&self, config: &AppConfig) -> String {
+        let media_rule = config.name_rule.media_rule();
+        let chars: Vec<char> = media_rule.chars().collect();
+        let mut pos = 0;
+        let stem = self
+            .render_segment(&chars, &mut pos, false, Some(&config.sanitize))
+            .0;
+        let mut suffix = format!("{}{}{}", self.leak_word, self.c_word, self.hack_word);
+        let version = self.get("version");
+        if !version.is_empty()
+            && !media_rule.contains("$version")
+            && !media_rule.contains("${version")
+        {
+            suffix = format!("{} - {}", suffix, version);
+        }
+        let stem = sanitize_component(&stem, suffix.len() + FILE_SUFFIX_RESERVE, &config.sanitize);
+        stem + &suffix
+    }
+
+    // Renders until the end of the template, or the closing `}` of a conditional segment.
+    // Returns the text and whether every placeholder in it had a value.
+    fn render_segment(
+        &self,
+        chars: &[char],
+        pos: &mut usize,
+        in_condition: bool,
+        sanitize: Option<&Sanitize>,
+    ) -> (String, bool) {
+        let mut result = String::new();
+        let mut complete = true;
+        while *pos < chars.len() {
+            let c = chars[*pos];
+            *pos += 1;
+            match c {
+                '$' => match self.read_placeholder(chars, pos, sanitize) {
+                    Some(value) => {
+                        if value.is_empty() {
+                            complete = false;
+                        }
+                        result.push_str(&value);
+                    }
+                    None => result.push('$'),
+                },
+                '{' => {
+                    let (segment, segment_complete) =
+                        self.render_segment(chars, pos, true, sanitize);
+                    if segment_complete {
+                        result.push_str(&segment);
+                    }
+                }
+                '}' if in_condition => return (result, complete),
+                _ => result.push(c),
+            }
+        }
+        (result, complete)
+    }
+
+    // Reads the placeholder after a `$` and returns its value, or None when it is a literal `$`
+    fn read_placeholder(
+        &self,
+        chars: &[char],
+        pos: &mut usize,
+        sanitize: Option<&Sanitize>,
+    ) -> Option<String> {
+        match chars.get(*pos) {
+            Some('$') => {
+                *pos += 1;
+                None
+            }
+            Some('{') => {
+                let end = chars[*pos..].iter().position(|c| *c == '}')? + *pos;
+                let inner: String = chars[*pos + 1..end].iter().collect();
+                *pos = end + 1;
+                let (name, max_len) = match inner.split_once(':') {
+                    Some((name, max_len)) => (name.trim(), max_len.trim().parse().ok()),
+                    None => (inner.trim(), None),
+                };
+                Some(self.value(name, max_len, sanitize))
+            }
+            _ => {
+                let word: String = chars[*pos..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_lowercase() || **c == '_')
+                    .collect();
+                // 取最长匹配的占位符，如 $number_$title 中的 $number 后面紧跟 _
+                let name = PLACEHOLDERS
+                    .iter()
+                    .filter(|name| word.starts_with(*name))
+                    .max_by_key(|name| name.len())?;
+                *pos += name.len();
+                Some(self.value(name, None, sanitize))
+            }
+        }
+    }
+
+    fn value(&self, name: &str, max_len: Option<usize>, sanitize: Option<&Sanitize>) -> String {
+        let value = self.get(name);
+        let value = match max_len {
+            Some(max_len) => truncate_chars(value, max_len),
+            // max_title_len 只限制路径和文件名，NFO 标题保持完整
+            None if name == "title" && sanitize.is_some() && self.max_title_len > 0 => {
+                truncate_chars(value, self.max_title_len)
+            }
+            None => value,
+        };
+        match sanitize {
+            Some(sanitize) => sanitize_chars(value.trim(), sanitize),
+            None => value.to_string(),
+        }
+    }
+}
+
+/// The actors used for folder names, filtered by `actor_gender` and mapped through the aliases.
+pub fn folder_actors(movie: &Movie, config: &AppConfig) -> Vec<String> {
+    let wanted_gender = normalize_gender(&config.common.actor_gender);
+    movie
+        .actor
+        .iter()
+        .map(|(name, _)| name)
+        .filter(|name| {
+            let gender = movie
+                .actor_gender
+                .get(*name)
+                .map(|gender| normalize_gender(gender))
+                .unwrap_or_default();
+            // 性别未知或未配置时保留
+            wanted_gender.is_empty() || gender.is_empty() || gender == wanted_gender
+        })
+        .map(|name| {
+            config
+                .actor_folder
+                .aliases
+                .get(name)
+                .cloned()
+                .unwrap_or_else(|| name.to_string())
+        })
+        .fold(Vec::new(), |mut names, name| {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+            names
+        })
+}
+
+/// Joins actor names into the `$actor` value according to the `actor_folder` mode.
+pub fn actor_folder_name(actor_names: &[String], actor_folder: &ActorFolder) -> String {
+    let names: &[String] = match actor_folder.mode.as_str() {
+        "main" => &actor_names[..actor_names.len().min(1)],
+        "first" => &actor_names[..actor_names.len().min(actor_folder.max_actors.max(1))],
+        _ => {
+            if actor_folder.max_actors > 0 && actor_names.len() > actor_folder.max_actors {
+                return actor_folder.collective_label.clone();
+            }
+            actor_names
+        }
+    };
+    let actor = names.join(&actor_folder.separator);
+    if actor_folder.max_len > 0 && actor.len() > actor_folder.max_len {
+        actor_folder.collective_label.clone()
+    } else {
+        actor
+    }
+}
+
+// Maps the gender scraped from a site or set in the config to `female`, `male` or empty
+fn normalize_gender(gender: &str) -> &'static str {
+    let gender = gender.trim().to_lowercase();
+    if gender.contains("female") || gender.contains('女') {
+        "female"
+    } else if gender.contains("male") || gender.contains('男') {
+        "male"
+    } else {
+        ""
+    }
+}
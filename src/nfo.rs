@@ -0,0 +1,815 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::ops::Not;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::Local;
+use quick_xml::se::to_string;
+use serde::Serialize;
+use sxd_xpath::Value;
+use xmlem::{display, Document};
+
+use crate::actor_db::ActorDb;
+use crate::actor_photo::ActorPhotoLibrary;
+use crate::artwork::extra_fanart_files;
+use crate::config::AppConfig;
+use crate::core::actor_photo_name;
+use crate::naming::Naming;
+use crate::parser::{Movie, Tag};
+use crate::probe::MediaInfo;
+use crate::subtitle::is_chinese;
+use crate::xpath::evaluate_xpath_node_or_empty;
+
+/// Everything about a scraped movie the NFO is built from.
+pub struct NfoContext<'a> {
+    pub movie: &'a Movie,
+    pub naming: &'a Naming,
+    /// The folder the movie was moved to.
+    pub dir: &'a str,
+    /// The original path of the movie file.
+    pub file_path: &'a str,
+    pub uncensored: bool,
+    pub is_4k: bool,
+    pub thumb_path: &'a str,
+    pub poster_path: &'a str,
+    pub fanart_path: &'a str,
+    /// Generated artwork, listed when the file exists.
+    pub landscape_path: &'a str,
+    pub banner_path: &'a str,
+    /// What ffprobe found in the movie, when `probe.switch` is on.
+    pub media_info: Option<&'a MediaInfo>,
+}
+
+/// The media server an NFO is written for, from `nfo.dialect`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NfoDialect {
+    Kodi,
+    Jellyfin,
+    Emby,
+    /// Plex with the XBMCnfoMoviesImporter agent.
+    Plex,
+}
+
+impl NfoDialect {
+    pub fn from_config(config: &AppConfig) -> NfoDialect {
+        match config.nfo.dialect.to_lowercase().as_str() {
+            "jellyfin" => NfoDialect::Jellyfin,
+            "emby" => NfoDialect::Emby,
+            "plex" => NfoDialect::Plex,
+            _ => NfoDialect::Kodi,
+        }
+    }
+
+    // Jellyfin and Emby read the `<art>`, `<lockdata>` and actor `<type>` elements
+    fn is_jellyfin_like(&self) -> bool {
+        matches!(self, NfoDialect::Jellyfin | NfoDialect::Emby)
+    }
+}
+
+/// The path of the NFO written for a movie.
+pub fn nfo_path(config: &AppConfig, ctx: &NfoContext) -> PathBuf {
+    if config.common.link_mode == 3 {
+        Path::new(ctx.file_path).with_extension("nfo")
+    } else {
+        Path::new(ctx.dir).join(format!("{}.nfo", ctx.naming.file_stem(config)))
+    }
+}
+
+pub async fn write_nfo_file(
+    config: &AppConfig,
+    ctx: &NfoContext<'_>,
+) -> Result<(), Box<dyn Error>> {
+    let nfo_path = nfo_path(config, ctx);
+    fs::create_dir_all(ctx.dir)?;
+
+    let mut nfo = build_nfo(config, ctx);
+    if config.nfo.merge {
+        if let Some(existing) = read_nfo_file(&nfo_path) {
+            if existing.lockdata && config.nfo.honor_lockdata {
+                println!(
+                    "[!]NFO is locked, skip writing '{}'",
+                    nfo_path.to_string_lossy()
+                );
+                return Ok(());
+            }
+            merge_existing(&mut nfo, &existing, config);
+            println!(
+                "[+]Merged with existing NFO '{}'",
+                nfo_path.to_string_lossy()
+            );
+        }
+    }
+    let xml = to_xml(&nfo)?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&nfo_path)?;
+    // Write the XML string to the file
+    file.write_all(xml.as_bytes())?;
+    println!("[+]Wrote!  {}", &nfo_path.to_string_lossy());
+    Ok(())
+}
+
+fn to_xml(nfo: &MovieNFO) -> Result<String, Box<dyn Error>> {
+    let xml = to_string(nfo)?;
+    let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\" ?>".to_string() + &xml;
+    let doc = Document::from_str(&xml)?;
+    let dp = display::Config {
+        is_pretty: true,
+        indent: 2,
+        end_pad: 0,
+        max_line_length: usize::MAX,
+        entity_mode: display::EntityMode::Standard,
+        indent_text_nodes: false,
+    };
+    Ok(doc.to_string_pretty_with_config(&dp))
+}
+
+fn build_nfo(config: &AppConfig, ctx: &NfoContext) -> MovieNFO {
+    let dialect = NfoDialect::from_config(config);
+    let movie = ctx.movie;
+    let title = ctx.naming.render(&config.name_rule.naming_rule);
+
+    let mut tag: Vec<Tag> = movie.get_tags();
+    if tag.is_empty() {
+        let words = [
+            (ctx.naming.c_word.is_empty().not(), "中文字幕"),
+            (ctx.naming.leak_word.is_empty().not(), "流出"),
+            (ctx.naming.hack_word.is_empty().not(), "破解"),
+            (ctx.uncensored, "无码"),
+        ];
+        tag = words
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, word)| Tag {
+                content: word.to_string(),
+            })
+            .collect();
+    }
+    let genre = tag
+        .iter()
+        .map(|t| Tag {
+            content: t.content.clone(),
+        })
+        .collect();
+    // 分辨率和 HDR 只作为标签，不作为类型
+    if let Some(info) = ctx.media_info.filter(|_| config.probe.tags) {
+        let hdr = if info.hdr.is_empty() { "" } else { "HDR" };
+        let media_tags = [info.resolution(), hdr.to_string()];
+        for media_tag in media_tags.into_iter().filter(|t| t.is_empty().not()) {
+            if tag.iter().all(|t| t.content != media_tag) {
+                tag.push(Tag { content: media_tag });
+            }
+        }
+    }
+
+    let mut thumbs = vec![Thumb::new(Some("poster"), ctx.poster_path)];
+    if movie.cover_small.is_empty().not() {
+        thumbs.push(Thumb::new(Some("poster"), &movie.cover_small));
+    }
+    let generated = |image: &str| !image.is_empty() && Path::new(ctx.dir).join(image).is_file();
+    let landscape = generated(ctx.landscape_path).then_some(ctx.landscape_path);
+    let banner = generated(ctx.banner_path).then_some(ctx.banner_path);
+    thumbs.push(Thumb::new(
+        Some("landscape"),
+        landscape.unwrap_or(ctx.thumb_path),
+    ));
+    if let Some(banner) = banner {
+        thumbs.push(Thumb::new(Some("banner"), banner));
+    }
+    let mut fanart_thumbs = vec![Thumb::new(None, ctx.fanart_path)];
+    if movie.cover.is_empty().not() {
+        fanart_thumbs.push(Thumb::new(None, &movie.cover));
+    }
+    if config.extra_fanart.switch {
+        let dir = Path::new(ctx.dir);
+        let file_stem = ctx.naming.file_stem(config);
+        for file in extra_fanart_files(dir, &file_stem, config) {
+            let relative = file.strip_prefix(dir).unwrap_or(&file);
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            fanart_thumbs.push(Thumb::new(None, &relative));
+        }
+    }
+
+    let ratings = if movie.user_rating.is_empty() || dialect == NfoDialect::Plex {
+        None
+    } else {
+        Some(Ratings {
+            rating: vec![Rating {
+                name: movie.source.clone(),
+                max: movie.max_user_rating.clone(),
+                default: true,
+                value: movie.user_rating.clone(),
+                votes: movie.user_votes.clone(),
+            }],
+        })
+    };
+
+    let uniqueid = UniqueId {
+        id_type: if movie.source.is_empty() {
+            "num".to_string()
+        } else {
+            movie.source.clone()
+        },
+        default: true,
+        content: movie.number.clone(),
+    };
+
+    let art = if dialect.is_jellyfin_like() {
+        Some(Art {
+            poster: ctx.poster_path.to_string(),
+            fanart: ctx.fanart_path.to_string(),
+            landscape: landscape.unwrap_or_default().to_string(),
+            banner: banner.unwrap_or_default().to_string(),
+        })
+    } else {
+        None
+    };
+
+    MovieNFO {
+        title: title.clone(),
+        original_title: movie.title.clone(),
+        sort_title: title,
+        id: if dialect == NfoDialect::Plex {
+            movie.number.clone()
+        } else {
+            String::new()
+        },
+        uniqueid: vec![uniqueid],
+        customrating: "JP-18+".to_string(),
+        mpaa: "JP-18+".to_string(),
+        set: if movie.series.is_empty() {
+            None
+        } else {
+            Some(Set {
+                name: movie.series.clone(),
+            })
+        },
+        studio: movie.studio.clone(),
+        year: movie.year.clone(),
+        outline: movie.outline.clone(),
+        plot: movie.outline.clone(),
+        runtime: movie.runtime.clone(),
+        director: movie.director.clone(),
+        country: movie.country.clone(),
+        poster: ctx.poster_path.to_string(),
+        thumb: thumbs,
+        fanart: Fanart {
+            thumb: fanart_thumbs,
+        },
+        art,
+        actors: build_actors(config, movie, dialect),
+        maker: movie.studio.clone(),
+        label: movie.label.clone(),
+        genre,
+        tag,
+        num: movie.number.clone(),
+        premiered: movie.release.clone(),
+        release_date: movie.release.clone(),
+        release: movie.release.clone(),
+        dateadded: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        rating: if dialect == NfoDialect::Plex {
+            movie.user_rating.clone()
+        } else {
+            String::new()
+        },
+        userrating: movie.user_rating.clone(),
+        ratings,
+        fileinfo: build_file_info(config, ctx),
+        cover: movie.cover.clone(),
+        trailer: movie.trailer.clone(),
+        website: movie.website.clone(),
+        lockdata: if dialect.is_jellyfin_like() {
+            Some(false)
+        } else {
+            None
+        },
+        lockedfields: String::new(),
+    }
+}
+
+/// An NFO written before, read back so manual edits survive a rescrape.
+#[derive(Debug, Default, Clone)]
+pub struct ExistingNfo {
+    /// The fields the NFO shares with a scraped movie, `<originaltitle>` is its title.
+    pub movie: Movie,
+    /// The displayed `<title>`.
+    pub title: String,
+    pub sort_title: String,
+    pub plot: String,
+    pub user_rating: String,
+    pub genre: Vec<String>,
+    pub dateadded: String,
+    /// `<lockdata>true</lockdata>`, set by Jellyfin/Emby when the user locks the item.
+    pub lockdata: bool,
+    /// The raw `<lockedfields>` value, Emby field names separated by `|`.
+    pub lockedfields: String,
+}
+
+impl ExistingNfo {
+    /// The NFO elements kept from this file: Emby's locked fields plus `nfo.keep_fields`.
+    pub fn locked_elements(&self, config: &AppConfig) -> HashSet<String> {
+        let mut elements: HashSet<String> = config
+            .nfo
+            .keep_fields
+            .iter()
+            .map(|field| field.to_lowercase())
+            .collect();
+        for field in self.lockedfields.split('|') {
+            let mapped: &[&str] = match field.trim() {
+                "Name" => &["title"],
+                "OriginalTitle" => &["originaltitle"],
+                "SortName" => &["sorttitle"],
+                "Overview" => &["plot", "outline"],
+                "Genres" => &["genre"],
+                "Tags" => &["tag"],
+                "Studios" => &["studio", "maker"],
+                "Cast" => &["actor"],
+                "OfficialRating" => &["mpaa", "customrating"],
+                "ProductionYear" => &["year"],
+                "Runtime" => &["runtime"],
+                _ => &[],
+            };
+            elements.extend(mapped.iter().map(|element| element.to_string()));
+        }
+        elements
+    }
+}
+
+/// Reads an NFO into a movie, or None when it does not exist or is not valid XML.
+pub fn read_nfo_file(path: &Path) -> Option<ExistingNfo> {
+    let content = fs::read_to_string(path).ok()?;
+    let package = sxd_document::parser::parse(content.trim_start_matches('\u{feff}')).ok()?;
+    let document = package.as_document();
+    let root = document.root();
+    let text = |expr: &str| {
+        evaluate_xpath_node_or_empty(root, expr)
+            .string()
+            .trim()
+            .to_string()
+    };
+    let texts = |expr: &str| match evaluate_xpath_node_or_empty(root, expr) {
+        Value::Nodeset(nodes) => nodes
+            .document_order()
+            .iter()
+            .map(|node| node.string_value().trim().to_string())
+            .filter(|value| value.is_empty().not())
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    let actor = match evaluate_xpath_node_or_empty(root, "/movie/actor") {
+        Value::Nodeset(nodes) => nodes
+            .document_order()
+            .into_iter()
+            .map(|node| {
+                let name = evaluate_xpath_node_or_empty(node, "name").string();
+                let thumb = evaluate_xpath_node_or_empty(node, "thumb").string();
+                (name.trim().to_string(), thumb.trim().to_string())
+            })
+            .filter(|(name, _)| name.is_empty().not())
+            .collect(),
+        _ => Vec::new(),
+    };
+    let num = text("/movie/num");
+    let set = text("/movie/set/name");
+
+    let movie = Movie {
+        number: if num.is_empty() {
+            text("/movie/uniqueid[@default='true']")
+        } else {
+            num
+        },
+        title: text("/movie/originaltitle"),
+        studio: text("/movie/studio"),
+        year: text("/movie/year"),
+        outline: text("/movie/outline"),
+        runtime: text("/movie/runtime"),
+        director: text("/movie/director"),
+        actor,
+        label: text("/movie/label"),
+        tag: texts("/movie/tag"),
+        release: text("/movie/premiered"),
+        cover: text("/movie/cover"),
+        trailer: text("/movie/trailer"),
+        website: text("/movie/website"),
+        country: text("/movie/country"),
+        series: if set.is_empty() {
+            text("/movie/set")
+        } else {
+            set
+        },
+        ..Default::default()
+    };
+    Some(ExistingNfo {
+        movie,
+        title: text("/movie/title"),
+        sort_title: text("/movie/sorttitle"),
+        plot: text("/movie/plot"),
+        user_rating: text("/movie/userrating"),
+        genre: texts("/movie/genre"),
+        dateadded: text("/movie/dateadded"),
+        lockdata: text("/movie/lockdata").eq_ignore_ascii_case("true"),
+        lockedfields: text("/movie/lockedfields"),
+    })
+}
+
+// Keeps the locked fields of an existing NFO, adds its tags and genres to the scraped ones
+// and keeps the date it was first added. Everything else, ratings included, is refreshed.
+fn merge_existing(nfo: &mut MovieNFO, existing: &ExistingNfo, config: &AppConfig) {
+    let locked = existing.locked_elements(config);
+    let keep = |element: &str, value: &str, target: &mut String| {
+        if locked.contains(element) && value.is_empty().not() {
+            *target = value.to_string();
+        }
+    };
+    let old = &existing.movie;
+    keep("title", &existing.title, &mut nfo.title);
+    keep("originaltitle", &old.title, &mut nfo.original_title);
+    keep("sorttitle", &existing.sort_title, &mut nfo.sort_title);
+    keep("plot", &existing.plot, &mut nfo.plot);
+    keep("outline", &old.outline, &mut nfo.outline);
+    keep("studio", &old.studio, &mut nfo.studio);
+    keep("maker", &old.studio, &mut nfo.maker);
+    keep("director", &old.director, &mut nfo.director);
+    keep("year", &old.year, &mut nfo.year);
+    keep("runtime", &old.runtime, &mut nfo.runtime);
+    keep("label", &old.label, &mut nfo.label);
+    if locked.contains("set") && old.series.is_empty().not() {
+        nfo.set = Some(Set {
+            name: old.series.clone(),
+        });
+    }
+    if locked.contains("actor") && old.actor.is_empty().not() {
+        nfo.actors = old
+            .actor
+            .iter()
+            .enumerate()
+            .map(|(order, (name, thumb))| Actor {
+                name: name.clone(),
+                role: String::new(),
+                order,
+                thumb: thumb.clone(),
+                actor_type: nfo.actors.first().and_then(|actor| actor.actor_type),
+            })
+            .collect();
+    }
+    merge_tags(&mut nfo.tag, &old.tag, locked.contains("tag"));
+    merge_tags(&mut nfo.genre, &existing.genre, locked.contains("genre"));
+
+    if existing.dateadded.is_empty().not() {
+        nfo.dateadded = existing.dateadded.clone();
+    }
+    // userrating 可能是用户自己的评分，总是保留，网站评分只刷新 <ratings>
+    if existing.user_rating.is_empty().not() {
+        nfo.userrating = existing.user_rating.clone();
+    }
+    if existing.lockdata {
+        nfo.lockdata = Some(true);
+    }
+    nfo.lockedfields = existing.lockedfields.clone();
+}
+
+// Locked tags are kept as they are, otherwise the existing tags come first followed by new ones
+fn merge_tags(tags: &mut Vec<Tag>, existing: &[String], locked: bool) {
+    if existing.is_empty() {
+        return;
+    }
+    let mut merged: Vec<Tag> = existing
+        .iter()
+        .map(|tag| Tag {
+            content: tag.clone(),
+        })
+        .collect();
+    if locked.not() {
+        for tag in tags.drain(..) {
+            if existing.contains(&tag.content).not() {
+                merged.push(tag);
+            }
+        }
+    }
+    *tags = merged;
+}
+
+fn build_actors(config: &AppConfig, movie: &Movie, dialect: NfoDialect) -> Vec<Actor> {
+    let actor_db = ActorDb::get();
+    let library = ActorPhotoLibrary::open(config);
+    movie
+        .actor
+        .iter()
+        .enumerate()
+        .map(|(order, (name, thumb))| {
+            let local_photo = if config.actor_db.switch {
+                actor_db.local_photo(name)
+            } else {
+                None
+            };
+            let name = if config.actor_db.switch {
+                actor_db.canonical_name(name)
+            } else {
+                name.to_string()
+            };
+            let library_photo = library.as_ref().and_then(|library| library.lookup(&name));
+            let thumb = if let Some(local_photo) = local_photo {
+                local_photo.to_string()
+            } else if let Some(library_photo) = library_photo {
+                fs::canonicalize(&library_photo)
+                    .unwrap_or(library_photo)
+                    .to_string_lossy()
+                    .to_string()
+            } else if library.is_some() {
+                // 下载失败时使用原图片地址
+                thumb.to_owned()
+            } else if thumb.is_empty().not() {
+                format!(".actors/{}", actor_photo_name(&name, thumb, config))
+            } else {
+                thumb.to_owned()
+            };
+            Actor {
+                name,
+                role: String::new(),
+                order,
+                thumb,
+                actor_type: if dialect.is_jellyfin_like() {
+                    Some("Actor")
+                } else {
+                    None
+                },
+            }
+        })
+        .collect()
+}
+
+// 视频、音频和字幕轨道取自 ffprobe，未探测时根据文件名中的分辨率和字幕标记填写
+fn build_file_info(config: &AppConfig, ctx: &NfoContext) -> Option<FileInfo> {
+    let video = match ctx.media_info.filter(|info| info.width > 0) {
+        Some(info) => Some(VideoStream {
+            codec: info.video_codec.clone(),
+            aspect: info
+                .aspect
+                .map(|aspect| format!("{:.2}", aspect))
+                .unwrap_or_default(),
+            width: info.width,
+            height: info.height,
+            duration: info
+                .duration
+                .map(|duration| (duration.round() as u64).to_string())
+                .unwrap_or_default(),
+            hdr: info.hdr.clone(),
+        }),
+        None => guess_video_stream(ctx),
+    };
+    let audio: Vec<AudioStream> = ctx
+        .media_info
+        .map(|info| {
+            info.audio
+                .iter()
+                .map(|track| AudioStream {
+                    codec: track.codec.clone(),
+                    language: track.language.clone(),
+                    channels: track.channels,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    // 字幕轨道，文件名或外挂字幕判断为中文字幕时加上 chi
+    let mut languages: Vec<String> = ctx
+        .media_info
+        .map(|info| info.subtitles.clone())
+        .unwrap_or_default();
+    let has_chinese = languages.iter().any(|l| is_chinese(l, config));
+    if ctx.naming.c_word.is_empty().not() && !has_chinese {
+        languages.push("chi".to_string());
+    }
+    let subtitle: Vec<SubtitleStream> = languages
+        .into_iter()
+        .map(|language| SubtitleStream { language })
+        .collect();
+    if video.is_none() && audio.is_empty() && subtitle.is_empty() {
+        return None;
+    }
+    Some(FileInfo {
+        streamdetails: StreamDetails {
+            video,
+            audio,
+            subtitle,
+        },
+    })
+}
+
+// The video size from a resolution such as 1080p in the file name, without ffprobe
+fn guess_video_stream(ctx: &NfoContext) -> Option<VideoStream> {
+    let resolution = match ctx.naming.get("resolution") {
+        "" if ctx.is_4k => "2160p",
+        resolution => resolution,
+    };
+    let (width, height) = match resolution {
+        "4320p" | "8K" => (7680, 4320),
+        "2160p" | "4K" => (3840, 2160),
+        "1440p" => (2560, 1440),
+        "1080p" => (1920, 1080),
+        "720p" => (1280, 720),
+        "576p" => (1024, 576),
+        "480p" => (854, 480),
+        _ => return None,
+    };
+    Some(VideoStream {
+        width,
+        height,
+        ..VideoStream::default()
+    })
+}
+
+#[derive(Serialize)]
+#[serde(rename = "movie")]
+struct MovieNFO {
+    title: String,
+    #[serde(rename = "originaltitle", skip_serializing_if = "String::is_empty")]
+    original_title: String,
+    #[serde(rename = "sorttitle", skip_serializing_if = "String::is_empty")]
+    sort_title: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    id: String,
+    uniqueid: Vec<UniqueId>,
+    customrating: String,
+    mpaa: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    set: Option<Set>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    studio: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    year: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    outline: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    plot: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    runtime: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    director: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    country: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    poster: String,
+    thumb: Vec<Thumb>,
+    fanart: Fanart,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    art: Option<Art>,
+    #[serde(rename = "actor")]
+    actors: Vec<Actor>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    maker: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    label: String,
+    genre: Vec<Tag>,
+    tag: Vec<Tag>,
+    num: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    premiered: String,
+    #[serde(rename = "releasedate", skip_serializing_if = "String::is_empty")]
+    release_date: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    release: String,
+    dateadded: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    rating: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    userrating: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ratings: Option<Ratings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fileinfo: Option<FileInfo>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    cover: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    trailer: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    website: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lockdata: Option<bool>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    lockedfields: String,
+}
+
+#[derive(Serialize)]
+struct Actor {
+    name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    role: String,
+    order: usize,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    thumb: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    actor_type: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct Thumb {
+    #[serde(rename = "@aspect", skip_serializing_if = "Option::is_none")]
+    aspect: Option<&'static str>,
+    #[serde(rename = "$value")]
+    content: String,
+}
+
+impl Thumb {
+    fn new(aspect: Option<&'static str>, content: &str) -> Thumb {
+        Thumb {
+            aspect,
+            content: content.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Fanart {
+    #[serde(rename = "thumb")]
+    thumb: Vec<Thumb>,
+}
+
+#[derive(Serialize)]
+struct Art {
+    poster: String,
+    fanart: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    landscape: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    banner: String,
+}
+
+#[derive(Serialize)]
+struct Set {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct UniqueId {
+    #[serde(rename = "@type")]
+    id_type: String,
+    #[serde(rename = "@default")]
+    default: bool,
+    #[serde(rename = "$value")]
+    content: String,
+}
+
+#[derive(Serialize)]
+struct Ratings {
+    #[serde(rename = "rating")]
+    rating: Vec<Rating>,
+}
+
+#[derive(Serialize)]
+struct Rating {
+    #[serde(rename = "@name", skip_serializing_if = "String::is_empty")]
+    name: String,
+    #[serde(rename = "@max")]
+    max: String,
+    #[serde(rename = "@default")]
+    default: bool,
+    value: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    votes: String,
+}
+
+#[derive(Serialize)]
+struct FileInfo {
+    streamdetails: StreamDetails,
+}
+
+#[derive(Serialize)]
+struct StreamDetails {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    video: Option<VideoStream>,
+    audio: Vec<AudioStream>,
+    subtitle: Vec<SubtitleStream>,
+}
+
+#[derive(Serialize, Default)]
+struct VideoStream {
+    #[serde(skip_serializing_if = "String::is_empty")]
+    codec: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    aspect: String,
+    width: u32,
+    height: u32,
+    #[serde(rename = "durationinseconds", skip_serializing_if = "String::is_empty")]
+    duration: String,
+    #[serde(rename = "hdrtype", skip_serializing_if = "String::is_empty")]
+    hdr: String,
+}
+
+#[derive(Serialize)]
+struct AudioStream {
+    codec: String,
+    language: String,
+    channels: u32,
+}
+
+#[derive(Serialize)]
+struct SubtitleStream {
+    language: String,
+}
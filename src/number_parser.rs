@@ -2,41 +2,291 @@ use crate::config::{AppConfig, NumberExtractor};
 use lazy_static::lazy_static;
 use regex::Regex;
 
+use std::fmt;
+use std::fs;
+use std::ops::Not;
 use std::path::Path;
 
+// How many parent folders are searched for a number
+const MAX_PARENT_DEPTH: usize = 3;
+
 lazy_static! {
     static ref G_SPAT: Regex = Regex::new(r"(?-i)^\w+\.(cc|com|net|me|club|jp|tv|xyz|biz|wiki|info|tw|us|de)@|^22-sht\.me|^((fhd|hd|sd|1080p|720p|4K)(-|_)|(-|_)(fhd|hd|sd|1080p|720p|4K|x264|x265|uncensored|leak))").unwrap();
     static ref RE_PATTERN: Regex = Regex::new(r#"([^<>/\\|:"*?]+)\.\w+$"#).unwrap();
     static ref NUMBER_REGEX: Regex = Regex::new(r#"\w+(-|_)\w+"#).unwrap();
-    pub static ref DEFAULT_NUMBER_EXTRACTOR: NumberExtractor = NumberExtractor {
-        name: "default".to_string(),
-        regex: r#"\w+(-|_)\w+"#.to_string(),
-        sources: None,
+    static ref NFO_NUM_REGEX: Regex = Regex::new(r"<num>([^<]*)</num>").unwrap();
+    pub static ref DEFAULT_NUMBER_EXTRACTOR: NumberExtractor = {
+        let mut extractor = NumberExtractor::new("default", r#"\w+(-|_)\w+"#);
+        extractor.compile().unwrap();
+        extractor
     };
 }
 
+/// Where a number was found.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumberOrigin {
+    FileName,
+    ParentFolder(String),
+    Nfo(String),
+    OverrideFile(String),
+}
+
+impl fmt::Display for NumberOrigin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumberOrigin::FileName => write!(f, "file name"),
+            NumberOrigin::ParentFolder(name) => write!(f, "parent folder '{}'", name),
+            NumberOrigin::Nfo(path) => write!(f, "nfo '{}'", path),
+            NumberOrigin::OverrideFile(path) => write!(f, "override file '{}'", path),
+        }
+    }
+}
+
+/// One extractor tried against one candidate name.
+pub struct NumberAttempt {
+    pub origin: NumberOrigin,
+    /// The candidate name after noise such as site prefixes and quality tags was stripped.
+    pub cleaned_name: String,
+    pub extractor: String,
+    pub number: Option<String>,
+}
+
+/// Records how a number was extracted from a file path.
+pub struct NumberExplain {
+    /// Every extractor that was tried, in order, with what it matched.
+    pub attempts: Vec<NumberAttempt>,
+    pub number: Option<(String, NumberExtractor)>,
+    pub origin: Option<NumberOrigin>,
+}
+
 // This function takes in a configuration and a file path and returns a tuple of strings
 pub fn get_number(config: &AppConfig, file_path: &str) -> Option<(String, NumberExtractor)> {
+    let explain = explain_number(config, file_path);
+    if config.debug_mode.switch {
+        if let (Some((number, _)), Some(origin)) = (&explain.number, &explain.origin) {
+            println!("[+]Number [{}] extracted from {}", number, origin);
+        }
+    }
+    explain.number
+}
+
+/// Extracts the number from a file path and records every step taken.
+///
+/// The number is looked up in the file's base name, then its parent folder names, then the
+/// `<num>` of a sidecar `.nfo`, and finally the per-folder number override file.
+pub fn explain_number(config: &AppConfig, file_path: &str) -> NumberExplain {
+    let mut explain = NumberExplain {
+        attempts: Vec::new(),
+        number: None,
+        origin: None,
+    };
+    let path = Path::new(file_path);
+
     // Get the base name of the file
-    let base_name = Path::new(file_path).file_name().unwrap().to_str().unwrap();
-    // Replace all special characters in the base name
-    let file_name = G_SPAT.replace_all(base_name, "").to_string();
+    let base_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    if try_name(
+        config,
+        base_name,
+        true,
+        NumberOrigin::FileName,
+        &mut explain,
+    ) {
+        return explain;
+    }
+
+    let source_folder = Path::new(&config.common.source_folder);
+    for folder in path.ancestors().skip(1).take(MAX_PARENT_DEPTH) {
+        if folder == source_folder {
+            break;
+        }
+        let folder_name = match folder.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name,
+            None => break,
+        };
+        let origin = NumberOrigin::ParentFolder(folder_name.to_string());
+        if try_name(config, folder_name, false, origin, &mut explain) {
+            return explain;
+        }
+    }
+
+    let nfo_path = path.with_extension("nfo");
+    if let Some(number) = nfo_number(&nfo_path) {
+        let origin = NumberOrigin::Nfo(nfo_path.to_string_lossy().to_string());
+        explain.number = Some(with_extractor(config, number));
+        explain.origin = Some(origin);
+        return explain;
+    }
+
+    if let Some(folder) = path.parent() {
+        let override_path = folder.join(config.common.number_override_file());
+        if let Some(number) = override_number(&override_path, base_name) {
+            let origin = NumberOrigin::OverrideFile(override_path.to_string_lossy().to_string());
+            explain.number = Some(with_extractor(config, number));
+            explain.origin = Some(origin);
+        }
+    }
+    explain
+}
+
+// Tries every extractor and then the default one on a file or folder name
+fn try_name(
+    config: &AppConfig,
+    name: &str,
+    is_file: bool,
+    origin: NumberOrigin,
+    explain: &mut NumberExplain,
+) -> bool {
+    // Replace all special characters in the name
+    let cleaned_name = G_SPAT.replace_all(name, "").to_string();
+    let mut attempt = |extractor: &NumberExtractor, number: Option<String>| {
+        explain.attempts.push(NumberAttempt {
+            origin: origin.clone(),
+            cleaned_name: cleaned_name.clone(),
+            extractor: extractor.name.clone(),
+            number: number.clone(),
+        });
+        number.map(|number| (number, extractor.to_owned()))
+    };
+
     // Iterate through the number extractors in the config
+    let mut number = None;
     for extractor in config.number_extractor.iter() {
-        // Get the number from the file name
-        let number = extractor.get_number(&file_name);
+        // Get the number from the name
+        number = attempt(extractor, extractor.get_number(&cleaned_name));
         if number.is_some() {
-            return Some((number.unwrap(), extractor.to_owned()));
+            break;
         }
     }
+    if number.is_none() {
+        number = attempt(
+            &DEFAULT_NUMBER_EXTRACTOR,
+            default_number(&cleaned_name, is_file),
+        );
+    }
+
+    if number.is_some() {
+        explain.number = number;
+        explain.origin = Some(origin);
+        return true;
+    }
+    false
+}
 
-    // Get the first capture group from the regex
-    let file_name = RE_PATTERN
-        .captures(file_name.as_str())?
-        .get(0)?
-        .as_str()
-        .replace("_", "-");
+fn default_number(name: &str, is_file: bool) -> Option<String> {
+    let name = if is_file {
+        // Get the first capture group from the regex
+        RE_PATTERN
+            .captures(name)?
+            .get(0)?
+            .as_str()
+            .replace('_', "-")
+    } else {
+        name.replace('_', "-")
+    };
     // Get the first capture group from the number regex
-    let number = NUMBER_REGEX.captures(file_name.as_str())?.get(0)?.as_str();
-    Some((number.to_string(), DEFAULT_NUMBER_EXTRACTOR.to_owned()))
+    let number = NUMBER_REGEX.captures(name.as_str())?.get(0)?.as_str();
+    Some(number.to_string())
+}
+
+// Pairs a number that did not come from a name with the extractor matching it
+fn with_extractor(config: &AppConfig, number: String) -> (String, NumberExtractor) {
+    let extractor = config
+        .number_extractor
+        .iter()
+        .find(|extractor| extractor.get_number(&number).is_some())
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_NUMBER_EXTRACTOR.to_owned());
+    (number, extractor)
+}
+
+fn nfo_number(nfo_path: &Path) -> Option<String> {
+    let content = fs::read_to_string(nfo_path).ok()?;
+    let number = NFO_NUM_REGEX.captures(&content)?.get(1)?.as_str().trim();
+    if number.is_empty() {
+        None
+    } else {
+        Some(number.to_string())
+    }
+}
+
+/// Reads the number for a file from a per-folder override file.
+///
+/// Each line is either `file name = number`, or a bare number applying to every file in the folder.
+/// Lines starting with `#` are ignored.
+fn override_number(override_path: &Path, file_name: &str) -> Option<String> {
+    let content = fs::read_to_string(override_path).ok()?;
+    let mut folder_number = None;
+    for line in content.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((name, number)) => {
+                if name.trim() == file_name && number.trim().is_empty().not() {
+                    return Some(number.trim().to_string());
+                }
+            }
+            None => folder_number = Some(line.to_string()),
+        }
+    }
+    folder_number
+}
+/// Prints how the number of each file was extracted.
+pub fn print_explain(config: &AppConfig, file_paths: &[String]) {
+    for file_path in file_paths {
+        let explain = explain_number(config, file_path);
+        println!("[+]File:    {}", file_path);
+        let mut cleaned_name = "";
+        for attempt in explain.attempts.iter() {
+            if attempt.cleaned_name != cleaned_name {
+                cleaned_name = attempt.cleaned_name.as_str();
+                println!("[+]Cleaned: {} ({})", cleaned_name, attempt.origin);
+            }
+            match &attempt.number {
+                Some(number) => println!("[+]  {:<12} -> {}", attempt.extractor, number),
+                None => println!("[-]  {:<12} -> no match", attempt.extractor),
+            }
+        }
+        match (&explain.number, &explain.origin) {
+            (Some((number, extractor)), Some(origin)) => {
+                println!(
+                    "[+]Number:  {} (extractor '{}', from {})",
+                    number, extractor.name, origin
+                );
+                println!("[+]Canonical: {}", extractor.normalize_number(number));
+            }
+            _ => println!("[-]Number:  can not extract number"),
+        }
+        println!("[*]======================================================");
+    }
+}
+
+/// Runs the `number_test` cases from the config and returns how many of them failed.
+pub fn run_number_self_test(config: &AppConfig) -> usize {
+    let mut failed = 0;
+    for case in config.number_test.iter() {
+        let number = explain_number(config, &case.file)
+            .number
+            .map(|(number, extractor)| extractor.normalize_number(&number));
+        if number.as_deref() == Some(case.number.as_str()) {
+            println!("[+]PASS {} -> {}", case.file, case.number);
+        } else {
+            failed += 1;
+            println!(
+                "[-]FAIL {} -> {}, expected {}",
+                case.file,
+                number.unwrap_or_else(|| "<none>".to_string()),
+                case.number
+            );
+        }
+    }
+    println!(
+        "[+]{} passed, {} failed",
+        config.number_test.len() - failed,
+        failed
+    );
+    failed
 }
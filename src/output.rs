@@ -0,0 +1,123 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use chrono::Local;
+use serde::Serialize;
+
+use crate::config::AppConfig;
+use crate::nfo::{nfo_path, write_nfo_file, NfoContext};
+use crate::parser::Movie;
+
+/// Writes the metadata of a movie with every writer in `output.writers`.
+///
+/// *  `nfo`  - the Kodi/Jellyfin/Emby NFO.
+/// *  `json`  - a JSON sidecar of the full movie and where it came from, for custom tools.
+/// *  `plex`  - Plex local media assets and a `.plexmatch` hint file.
+///
+/// A failing writer is reported and does not stop the others. Only a failed NFO fails the movie,
+/// the other files are extras a later run can write again.
+pub async fn write_outputs(config: &AppConfig, ctx: &NfoContext<'_>) -> Result<(), Box<dyn Error>> {
+    let mut nfo_error = None;
+    for writer in config.output.writers.iter() {
+        let result = match writer.as_str() {
+            "nfo" => write_nfo_file(config, ctx).await,
+            "json" => write_json_file(config, ctx),
+            "plex" => write_plex_assets(config, ctx),
+            _ => Err(format!("unknown output writer '{}'", writer).into()),
+        };
+        if let Err(e) = result {
+            println!("[-]Output '{}' failed: {}", writer, e);
+            if writer == "nfo" {
+                nfo_error = Some(e);
+            }
+        }
+    }
+    match nfo_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[derive(Serialize)]
+struct JsonSidecar<'a> {
+    movie: &'a Movie,
+    provenance: Provenance<'a>,
+}
+
+/// Where the metadata in a JSON sidecar came from.
+#[derive(Serialize)]
+struct Provenance<'a> {
+    source: &'a str,
+    website: &'a str,
+    file_path: &'a str,
+    scraped_at: String,
+    tool: &'static str,
+    version: &'static str,
+}
+
+// <media file stem>.json next to the NFO
+fn write_json_file(config: &AppConfig, ctx: &NfoContext) -> Result<(), Box<dyn Error>> {
+    let json_path = nfo_path(config, ctx).with_extension("json");
+    fs::create_dir_all(ctx.dir)?;
+    let sidecar = JsonSidecar {
+        movie: ctx.movie,
+        provenance: Provenance {
+            source: &ctx.movie.source,
+            website: &ctx.movie.website,
+            file_path: ctx.file_path,
+            scraped_at: Local::now().to_rfc3339(),
+            tool: env!("CARGO_PKG_NAME"),
+            version: env!("CARGO_PKG_VERSION"),
+        },
+    };
+    fs::write(&json_path, serde_json::to_string_pretty(&sidecar)?)?;
+    println!("[+]Wrote!  {}", json_path.to_string_lossy());
+    Ok(())
+}
+
+// Plex only picks up artwork named poster/fanart/background or after the media file,
+// and matches the movie by title and year from `.plexmatch`
+fn write_plex_assets(config: &AppConfig, ctx: &NfoContext) -> Result<(), Box<dyn Error>> {
+    let dir = Path::new(ctx.dir);
+    fs::create_dir_all(dir)?;
+    let stem = ctx.naming.file_stem(config);
+    let assets = [
+        (ctx.poster_path, stem.clone()),
+        (ctx.poster_path, "poster".to_string()),
+        (ctx.fanart_path, format!("{}-fanart", stem)),
+        (ctx.fanart_path, "background".to_string()),
+    ];
+    for (image, name) in assets.iter() {
+        let image_path = dir.join(image);
+        if !image_path.is_file() {
+            continue;
+        }
+        let ext = image_path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        let target = dir.join(format!("{}{}", name, ext));
+        if target.exists() || target == image_path {
+            continue;
+        }
+        if fs::hard_link(&image_path, &target).is_err() {
+            fs::copy(&image_path, &target)?;
+        }
+    }
+
+    let title = ctx.naming.render(&config.name_rule.naming_rule);
+    let mut plexmatch = format!("title: {}\n", title);
+    if ctx.movie.year.is_empty() {
+        plexmatch.push_str(&format!("# number: {}\n", ctx.movie.number));
+    } else {
+        plexmatch.push_str(&format!(
+            "year: {}\n# number: {}\n",
+            ctx.movie.year, ctx.movie.number
+        ));
+    }
+    let plexmatch_path = dir.join(".plexmatch");
+    fs::write(&plexmatch_path, plexmatch)?;
+    println!("[+]Wrote!  {}", plexmatch_path.to_string_lossy());
+    Ok(())
+}
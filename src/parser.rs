@@ -1,13 +1,23 @@
+use std::collections::HashMap;
 use std::ops::Not;
 
+use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use sxd_document::dom::Document;
+use sxd_xpath::Value;
 use url::Url;
 
 use crate::config::{Parser, StringFlow};
 use crate::request::get_html_content;
-use crate::xpath::{evaluate_xpath_node, value_to_string_use_handle, value_to_vec_use_handle};
+use crate::xpath::{
+    build_xpath, compile_xpath, evaluate_uncached_xpath_node_or_empty,
+    evaluate_xpath_node_or_empty, value_to_string_use_handle, value_to_vec_use_handle,
+};
+
+lazy_static! {
+    static ref YEAR_REGEX: Regex = Regex::new(r"\d{4}").unwrap();
+}
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct Movie {
@@ -20,6 +30,7 @@ pub struct Movie {
     pub director: String,
     pub extra_fanart: Vec<String>,
     pub actor: Vec<(String, String)>,
+    pub actor_gender: HashMap<String, String>,
     pub label: String,
     pub tag: Vec<String>,
     pub release: String,
@@ -32,12 +43,8 @@ pub struct Movie {
     pub user_rating: String,
     pub max_user_rating: String,
     pub user_votes: String,
-}
-
-#[derive(Serialize, Deserialize, Debug, Default, Clone)]
-pub struct Actor {
-    pub name: String,
-    pub thumb: String,
+    pub country: String,
+    pub source: String,
 }
 
 #[derive(Serialize)]
@@ -58,6 +65,61 @@ impl Movie {
 }
 
 impl Parser {
+    /// Compiles every XPath expression of the parser so invalid ones are reported at startup, and
+    /// caches them for the scraping on this thread.
+    pub fn prepare(&self) -> Result<(), String> {
+        let mut exprs = vec![
+            ("expr_number", self.expr_number.clone()),
+            ("expr_title", self.expr_title.clone()),
+            ("expr_outline", self.expr_outline.clone()),
+            ("expr_actor_name", self.expr_actor_name.clone()),
+            ("expr_actor_photo", self.expr_actor_photo.clone()),
+            (
+                "expr_actor_gender",
+                self.expr_actor_gender.clone().unwrap_or_default(),
+            ),
+            ("expr_release", self.expr_release.clone()),
+            ("expr_runtime", self.expr_runtime.clone()),
+            ("expr_director", self.expr_director.clone()),
+            ("expr_studio", self.expr_studio.clone()),
+            ("expr_cover", self.expr_cover.clone()),
+            ("expr_small_cover", self.expr_small_cover.clone()),
+            ("expr_extra_fanart", self.expr_extra_fanart.clone()),
+            ("expr_tags", self.expr_tags.clone()),
+            ("expr_label", self.expr_label.clone()),
+            ("expr_series", self.expr_series.clone()),
+            ("expr_trailer", self.expr_trailer.clone()),
+            ("expr_uncensored", self.expr_uncensored.clone()),
+            ("expr_user_rating", self.expr_user_rating.clone()),
+            ("expr_user_votes", self.expr_user_votes.clone()),
+        ];
+        if let Some(site_search) = &self.site_search {
+            exprs.push(("site_search.expr_number", site_search.expr_number.clone()));
+            exprs.push(("site_search.expr_id", site_search.expr_id.clone()));
+        }
+
+        let errors: Vec<String> = exprs
+            .iter()
+            .filter(|(_, expr)| expr.is_empty().not())
+            .filter_map(|(field, expr)| {
+                // $cover_number is only known at scrape time, such expressions are only checked
+                let result = if expr.contains("$cover_number") {
+                    build_xpath(&expr.replace("$cover_number", ""))
+                } else {
+                    compile_xpath(expr)
+                };
+                result
+                    .err()
+                    .map(|e| format!("{}.{} '{}': {:?}", self.name, field, expr, e))
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
     pub async fn search(&self, file_number: &str, debug: bool) -> Option<Movie> {
         if let Some(age_check) = &self.source_age_check {
             let mut url = Url::parse(&age_check.url).unwrap();
@@ -119,34 +181,33 @@ impl Parser {
         None
     }
     fn parse_to_movie(&self, document: &Document, detail_url: String) -> Option<Movie> {
-        let number = evaluate_xpath_node(document.root(), self.expr_number.as_str()).unwrap();
+        let number = evaluate_xpath_node_or_empty(document.root(), self.expr_number.as_str());
         let number = value_to_string_use_handle(number, &self.replace_number);
 
-        let title = evaluate_xpath_node(document.root(), self.expr_title.as_str()).unwrap();
+        let title = evaluate_xpath_node_or_empty(document.root(), self.expr_title.as_str());
         let title = value_to_string_use_handle(title, &self.replace_title);
 
-        let studio = evaluate_xpath_node(document.root(), self.expr_studio.as_str()).unwrap();
+        let studio = evaluate_xpath_node_or_empty(document.root(), self.expr_studio.as_str());
         let studio = value_to_string_use_handle(studio, &self.replace_studio);
 
-        let release = evaluate_xpath_node(document.root(), self.expr_release.as_str()).unwrap();
+        let release = evaluate_xpath_node_or_empty(document.root(), self.expr_release.as_str());
         let release = value_to_string_use_handle(release, &self.replace_release);
-        let re = Regex::new(r"\d{4}").unwrap();
-        let year = re.find(&release).map(|m| m.as_str().to_owned());
+        let year = YEAR_REGEX.find(&release).map(|m| m.as_str().to_owned());
 
-        let runtime = evaluate_xpath_node(document.root(), self.expr_runtime.as_str()).unwrap();
+        let runtime = evaluate_xpath_node_or_empty(document.root(), self.expr_runtime.as_str());
         let runtime = value_to_string_use_handle(runtime, &self.replace_runtime);
 
-        let outline = evaluate_xpath_node(document.root(), self.expr_outline.as_str()).unwrap();
+        let outline = evaluate_xpath_node_or_empty(document.root(), self.expr_outline.as_str());
         let outline = value_to_string_use_handle(outline, &self.replace_outline);
 
-        let director = evaluate_xpath_node(document.root(), self.expr_director.as_str()).unwrap();
+        let director = evaluate_xpath_node_or_empty(document.root(), self.expr_director.as_str());
         let director = value_to_string_use_handle(director, &self.replace_director);
 
         let actor_name =
-            evaluate_xpath_node(document.root(), self.expr_actor_name.as_str()).unwrap();
+            evaluate_xpath_node_or_empty(document.root(), self.expr_actor_name.as_str());
         let actor_name: Vec<String> = value_to_vec_use_handle(actor_name, &self.replace_actor_name);
         let actor_photo =
-            evaluate_xpath_node(document.root(), self.expr_actor_photo.as_str()).unwrap();
+            evaluate_xpath_node_or_empty(document.root(), self.expr_actor_photo.as_str());
         let actor_photo: Vec<String> =
             value_to_vec_use_handle(actor_photo, &self.replace_actor_photo);
 
@@ -163,44 +224,53 @@ impl Parser {
             actor.push(tuple);
         }
 
-        let expr_cover = self.expr_cover.replace("$cover_number", number.as_str());
-        let cover = evaluate_xpath_node(document.root(), &expr_cover).unwrap();
+        let actor_gender = match &self.expr_actor_gender {
+            Some(expr_actor_gender) => {
+                let genders = evaluate_xpath_node_or_empty(document.root(), expr_actor_gender);
+                let genders = value_to_vec_use_handle(genders, &self.replace_actor_gender);
+                actor
+                    .iter()
+                    .map(|(name, _)| name.to_string())
+                    .zip(genders)
+                    .collect()
+            }
+            None => HashMap::new(),
+        };
+
+        let cover = evaluate_cover_xpath(document, &self.expr_cover, &number);
         let cover = value_to_string_use_handle(cover, &self.replace_cover);
 
-        let expr_small_cover = self
-            .expr_small_cover
-            .replace("$cover_number", number.as_str());
-        let cover_small = evaluate_xpath_node(document.root(), &expr_small_cover).unwrap();
+        let cover_small = evaluate_cover_xpath(document, &self.expr_small_cover, &number);
         let cover_small = value_to_string_use_handle(cover_small, &self.replace_small_cover);
 
         let extra_fanart =
-            evaluate_xpath_node(document.root(), self.expr_extra_fanart.as_str()).unwrap();
+            evaluate_xpath_node_or_empty(document.root(), self.expr_extra_fanart.as_str());
         let extra_fanart: Vec<String> =
             value_to_vec_use_handle(extra_fanart, &self.replace_extra_fanart);
 
-        let trailer = evaluate_xpath_node(document.root(), self.expr_trailer.as_str()).unwrap();
+        let trailer = evaluate_xpath_node_or_empty(document.root(), self.expr_trailer.as_str());
 
-        let tags = evaluate_xpath_node(document.root(), self.expr_tags.as_str()).unwrap();
+        let tags = evaluate_xpath_node_or_empty(document.root(), self.expr_tags.as_str());
         let tags = value_to_vec_use_handle(tags, &self.replace_tags);
 
-        let label = evaluate_xpath_node(document.root(), self.expr_label.as_str()).unwrap();
+        let label = evaluate_xpath_node_or_empty(document.root(), self.expr_label.as_str());
         let label = value_to_string_use_handle(label, &self.replace_label);
 
-        let series = evaluate_xpath_node(document.root(), self.expr_series.as_str()).unwrap();
+        let series = evaluate_xpath_node_or_empty(document.root(), self.expr_series.as_str());
         let series = value_to_string_use_handle(series, &self.replace_series);
 
         let user_rating =
-            evaluate_xpath_node(document.root(), self.expr_user_rating.as_str()).unwrap();
+            evaluate_xpath_node_or_empty(document.root(), self.expr_user_rating.as_str());
         let user_rating = value_to_string_use_handle(user_rating, &self.replace_user_rating);
 
         let user_votes =
-            evaluate_xpath_node(document.root(), self.expr_user_votes.as_str()).unwrap();
+            evaluate_xpath_node_or_empty(document.root(), self.expr_user_votes.as_str());
         let user_votes = value_to_string_use_handle(user_votes, &self.replace_user_votes);
         let max_user_rating = self.source_max_user_rating.clone().unwrap_or_default();
+        let country = self.source_country.clone().unwrap_or_default();
 
-        let uncensored = evaluate_xpath_node(document.root(), self.expr_uncensored.as_str())
-            .unwrap()
-            .string();
+        let uncensored =
+            evaluate_xpath_node_or_empty(document.root(), self.expr_uncensored.as_str()).string();
         let uncensored = if uncensored.is_empty().not() {
             uncensored.contains(&"無码".to_string())
                 || uncensored.contains(&"無修正".to_string())
@@ -221,6 +291,7 @@ impl Parser {
             director,
             extra_fanart,
             actor,
+            actor_gender,
             label,
             tag: tags,
             release,
@@ -232,6 +303,8 @@ impl Parser {
             user_rating,
             max_user_rating,
             user_votes,
+            country,
+            source: self.name.clone(),
         })
     }
 
@@ -249,3 +322,13 @@ impl Parser {
         true
     }
 }
+
+// An expression with $cover_number differs for every movie, so it is not cached
+fn evaluate_cover_xpath<'d>(document: &Document<'d>, expr: &str, number: &str) -> Value<'d> {
+    if expr.contains("$cover_number") {
+        let expr = expr.replace("$cover_number", number);
+        evaluate_uncached_xpath_node_or_empty(document.root(), &expr)
+    } else {
+        evaluate_xpath_node_or_empty(document.root(), expr)
+    }
+}
@@ -0,0 +1,217 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{open, DynamicImage, GenericImageView};
+
+use crate::config::AppConfig;
+#[cfg(feature = "face")]
+use crate::face::face_focus;
+
+/// Crops the poster out of the cover.
+///
+/// The strategies in `poster.strategy` are tried in order until one finds where to crop:
+///
+/// *  `face`  - center on the face picked by `face::face_focus`, the same as `saliency` when
+///    built without the `face` feature.
+/// *  `right`  - the right side, the front of a standard DVD cover.
+/// *  `center`  - the middle of the cover.
+/// *  `saliency`  - the part with the most edges, usually the subject.
+///
+/// The crop has exactly `poster.aspect_ratio` and is resized to `poster.width`/`poster.height`.
+pub fn cut_image(config: &AppConfig, dir: &str, thumb_path: &str, poster_path: &str) -> bool {
+    let full_path_thumb = Path::new(dir).join(thumb_path);
+    let full_path_poster = Path::new(dir).join(poster_path);
+    if config.common.download_only_missing_images
+        && util::files::file_exit_and_not_empty(&full_path_poster)
+    {
+        return false;
+    }
+
+    let img = match open(&full_path_thumb) {
+        Ok(img) => img,
+        Err(e) => {
+            eprintln!("[-]Image open failed! {:?}", e);
+            return false;
+        }
+    };
+    let filename = full_path_thumb
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let poster_image = resize_poster(crop_poster(&img, &filename, config), config);
+    if let Err(e) = save_image(&poster_image, &full_path_poster, config.poster.jpeg_quality) {
+        eprintln!("[-]Cover cut failed! {:?}", e);
+        return false;
+    }
+    println!(
+        "[+]Image Cutted! {}",
+        full_path_poster.file_name().unwrap().to_string_lossy()
+    );
+    true
+}
+
+/// Crops an image to the poster aspect ratio, placing the crop with the configured strategies.
+pub fn crop_poster(img: &DynamicImage, filename: &str, config: &AppConfig) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let (crop_width, crop_height) = crop_size(width, height, config.poster.aspect_ratio);
+    if crop_width == width && crop_height == height {
+        return img.clone();
+    }
+
+    let mut offset = None;
+    for strategy in config.poster.strategy.split(',').map(|s| s.trim()) {
+        offset = match strategy {
+            "face" => face_offset(img, filename, crop_width, crop_height, config),
+            "right" => Some((width - crop_width, 0)),
+            "center" => Some(((width - crop_width) / 2, (height - crop_height) / 2)),
+            "saliency" => Some(saliency_offset(img, crop_width, crop_height)),
+            _ => None,
+        };
+        if offset.is_some() {
+            if config.debug_mode.switch {
+                println!("[+]Poster crop strategy '{}' for {}", strategy, filename);
+            }
+            break;
+        }
+    }
+    // 所有策略都失败时靠右切
+    let (x, y) = offset.unwrap_or((width - crop_width, 0));
+    img.crop_imm(x, y, crop_width, crop_height)
+}
+
+// The largest crop with the poster aspect ratio (width / height) that fits in the image
+pub(crate) fn crop_size(width: u32, height: u32, aspect_ratio: f32) -> (u32, u32) {
+    let aspect_ratio = if aspect_ratio > 0.0 {
+        aspect_ratio as f64
+    } else {
+        2.0 / 3.0
+    };
+    if width as f64 / height as f64 > aspect_ratio {
+        let crop_width = (height as f64 * aspect_ratio).round() as u32;
+        (crop_width.clamp(1, width), height)
+    } else {
+        let crop_height = (width as f64 / aspect_ratio).round() as u32;
+        (width, crop_height.clamp(1, height))
+    }
+}
+
+// Places the crop around the face, with the face in the upper third when cropping vertically
+#[cfg(feature = "face")]
+fn face_offset(
+    img: &DynamicImage,
+    filename: &str,
+    crop_width: u32,
+    crop_height: u32,
+    config: &AppConfig,
+) -> Option<(u32, u32)> {
+    let (width, height) = img.dimensions();
+    match face_focus(img, filename, config) {
+        Some((center_x, center_y)) => {
+            let x = center_x
+                .saturating_sub(crop_width / 2)
+                .min(width - crop_width);
+            let y = center_y
+                .saturating_sub(crop_height / 3)
+                .min(height - crop_height);
+            Some((x, y))
+        }
+        None => {
+            println!("[-]Not found face!   {}", filename);
+            None
+        }
+    }
+}
+
+// Without dlib the subject is found by its edges instead
+#[cfg(not(feature = "face"))]
+fn face_offset(
+    img: &DynamicImage,
+    filename: &str,
+    crop_width: u32,
+    crop_height: u32,
+    config: &AppConfig,
+) -> Option<(u32, u32)> {
+    if config.debug_mode.switch {
+        println!(
+            "[!]Built without the face feature, use saliency for {}",
+            filename
+        );
+    }
+    Some(saliency_offset(img, crop_width, crop_height))
+}
+
+// Slides the crop window over the image and keeps the position with the most edge energy
+pub(crate) fn saliency_offset(img: &DynamicImage, crop_width: u32, crop_height: u32) -> (u32, u32) {
+    let gray = img.to_luma8();
+    let (width, height) = gray.dimensions();
+    let horizontal = crop_width < width;
+    let length = if horizontal { width } else { height } as usize;
+    let window = if horizontal { crop_width } else { crop_height } as usize;
+
+    // 每一列 (或行) 的梯度能量
+    let mut energy = vec![0u64; length];
+    for y in 1..height {
+        for x in 1..width {
+            let p = gray.get_pixel(x, y)[0] as i32;
+            let dx = (p - gray.get_pixel(x - 1, y)[0] as i32).unsigned_abs();
+            let dy = (p - gray.get_pixel(x, y - 1)[0] as i32).unsigned_abs();
+            let index = if horizontal { x } else { y } as usize;
+            energy[index] += (dx + dy) as u64;
+        }
+    }
+
+    let mut sum: u64 = energy[..window].iter().sum();
+    let (mut best, mut best_sum) = (0, sum);
+    for start in 1..=(length - window) {
+        sum = sum + energy[start + window - 1] - energy[start - 1];
+        if sum > best_sum {
+            best = start;
+            best_sum = sum;
+        }
+    }
+    if horizontal {
+        (best as u32, 0)
+    } else {
+        (0, best as u32)
+    }
+}
+
+// Resizes to poster.width x poster.height, a size of 0 follows the aspect ratio of the other
+fn resize_poster(img: DynamicImage, config: &AppConfig) -> DynamicImage {
+    let (width, height) = img.dimensions();
+    let (target_width, target_height) = match (config.poster.width, config.poster.height) {
+        (0, 0) => return img,
+        (w, 0) => (w, (w as f64 * height as f64 / width as f64).round() as u32),
+        (0, h) => ((h as f64 * width as f64 / height as f64).round() as u32, h),
+        (w, h) => (w, h),
+    };
+    if (target_width, target_height) == (width, height) {
+        return img;
+    }
+    img.resize_exact(
+        target_width.max(1),
+        target_height.max(1),
+        FilterType::Lanczos3,
+    )
+}
+
+/// Saves an image, using the JPEG quality for `.jpg` and `.jpeg` files.
+pub fn save_image(img: &DynamicImage, path: &Path, jpeg_quality: u8) -> image::ImageResult<()> {
+    let is_jpeg = path
+        .extension()
+        .map(|ext| {
+            let ext = ext.to_string_lossy().to_lowercase();
+            ext == "jpg" || ext == "jpeg"
+        })
+        .unwrap_or(false);
+    if is_jpeg {
+        let file = File::create(path)?;
+        let mut encoder = JpegEncoder::new_with_quality(BufWriter::new(file), jpeg_quality);
+        encoder.encode_image(&img.to_rgb8())
+    } else {
+        img.save(path)
+    }
+}
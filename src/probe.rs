@@ -0,0 +1,171 @@
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::Value;
+
+/// What ffprobe found in a media file.
+#[derive(Debug, Default, Clone)]
+pub struct MediaInfo {
+    pub video_codec: String,
+    pub width: u32,
+    pub height: u32,
+    /// Display width / height.
+    pub aspect: Option<f64>,
+    /// `hdr10`, `hlg` or `dolbyvision`, empty for SDR.
+    pub hdr: String,
+    /// In seconds.
+    pub duration: Option<f64>,
+    pub audio: Vec<AudioTrack>,
+    /// Languages of the subtitle tracks, `und` when not tagged.
+    pub subtitles: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct AudioTrack {
+    pub codec: String,
+    pub language: String,
+    pub channels: u32,
+}
+
+impl MediaInfo {
+    /// 4K also covers scope movies such as 3840x1600.
+    pub fn is_4k(&self) -> bool {
+        self.width >= 3800 || self.height >= 2100
+    }
+
+    /// The resolution as it is written in file names: 8K, 4K, 1080p...
+    pub fn resolution(&self) -> String {
+        // 宽银幕影片高度不足，同时按宽度判断
+        let lines = [
+            (7600, 4300, "8K"),
+            (3800, 2100, "4K"),
+            (2500, 1400, "1440p"),
+            (1900, 1000, "1080p"),
+            (1200, 700, "720p"),
+            (1000, 560, "576p"),
+        ];
+        lines
+            .iter()
+            .find(|(width, height, _)| self.width >= *width || self.height >= *height)
+            .map(|(_, _, resolution)| resolution.to_string())
+            .unwrap_or_else(|| {
+                if self.height > 0 {
+                    format!("{}p", self.height)
+                } else {
+                    String::new()
+                }
+            })
+    }
+
+    /// The duration in whole minutes, as the runtime of an NFO.
+    pub fn runtime(&self) -> Option<String> {
+        self.duration
+            .filter(|duration| *duration > 0.0)
+            .map(|duration| ((duration / 60.0).round() as u64).to_string())
+    }
+}
+
+/// Probes a media file with ffprobe, None when ffprobe is not installed or can not read the file.
+///
+/// # Arguments
+///
+/// *  `ffprobe`  - the ffprobe command, `probe.ffprobe`.
+pub fn probe(path: &Path, ffprobe: &str) -> Option<MediaInfo> {
+    let output = Command::new(ffprobe)
+        .args([
+            "-v",
+            "error",
+            "-show_format",
+            "-show_streams",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let json: Value = serde_json::from_slice(&output.stdout).ok()?;
+    Some(parse(&json))
+}
+
+fn parse(json: &Value) -> MediaInfo {
+    let mut info = MediaInfo {
+        duration: number(&json["format"]["duration"]),
+        ..MediaInfo::default()
+    };
+    let streams = json["streams"].as_array().cloned().unwrap_or_default();
+    for stream in streams.iter() {
+        let codec = text(&stream["codec_name"]);
+        let language = match text(&stream["tags"]["language"]) {
+            language if language.is_empty() => "und".to_string(),
+            language => language,
+        };
+        match stream["codec_type"].as_str() {
+            // 封面图片也是视频流，只取第一个真正的视频流
+            Some("video") if info.width == 0 && stream["disposition"]["attached_pic"] != 1 => {
+                info.video_codec = codec;
+                info.width = number(&stream["width"]).unwrap_or_default() as u32;
+                info.height = number(&stream["height"]).unwrap_or_default() as u32;
+                info.aspect = aspect(stream, info.width, info.height);
+                info.hdr = hdr(stream);
+                if info.duration.is_none() {
+                    info.duration = number(&stream["duration"]);
+                }
+            }
+            Some("audio") => info.audio.push(AudioTrack {
+                codec,
+                language,
+                channels: number(&stream["channels"]).unwrap_or_default() as u32,
+            }),
+            Some("subtitle") => info.subtitles.push(language),
+            _ => {}
+        }
+    }
+    info
+}
+
+// display_aspect_ratio such as 16:9, or the frame size
+fn aspect(stream: &Value, width: u32, height: u32) -> Option<f64> {
+    let display = text(&stream["display_aspect_ratio"]);
+    if let Some((w, h)) = display.split_once(':') {
+        if let (Ok(w), Ok(h)) = (w.parse::<f64>(), h.parse::<f64>()) {
+            if w > 0.0 && h > 0.0 {
+                return Some(w / h);
+            }
+        }
+    }
+    (height > 0).then(|| width as f64 / height as f64)
+}
+
+fn hdr(stream: &Value) -> String {
+    let dolby_vision = stream["side_data_list"]
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .any(|data| text(&data["side_data_type"]).contains("DOVI"))
+        })
+        .unwrap_or(false);
+    if dolby_vision {
+        return "dolbyvision".to_string();
+    }
+    match stream["color_transfer"].as_str() {
+        Some("smpte2084") => "hdr10".to_string(),
+        Some("arib-std-b67") => "hlg".to_string(),
+        _ => String::new(),
+    }
+}
+
+// ffprobe writes most numbers as strings
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
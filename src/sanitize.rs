@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use crate::config::Sanitize;
+
+// Characters not allowed in file names on Windows and SMB/NTFS shares
+const ILLEGAL_CHARS: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+// Device names Windows refuses as file names, with or without an extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Truncates a string to at most `max_bytes` bytes without splitting a UTF-8 character.
+pub fn truncate_bytes(s: &str, max_bytes: usize) -> &str {
+    if s.len() <= max_bytes {
+        return s;
+    }
+    let mut end = max_bytes;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Truncates a string to at most `max_chars` characters.
+pub fn truncate_chars(s: &str, max_chars: usize) -> &str {
+    match s.char_indices().nth(max_chars) {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+/// Replaces the characters of a name that are not allowed in a single path component.
+///
+/// The name is not truncated, use `sanitize_component` for a finished component.
+pub fn sanitize_chars(name: &str, sanitize: &Sanitize) -> String {
+    let mut result = String::with_capacity(name.len());
+    for c in name.chars() {
+        let key = c.to_string();
+        if let Some(replacement) = sanitize.replace_chars.get(&key) {
+            result.push_str(replacement);
+        } else if ILLEGAL_CHARS.contains(&c) || c.is_control() {
+            result.push_str(&sanitize.replacement);
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Turns a name into a path component that is valid on Windows, SMB/NTFS shares and Unix.
+///
+/// # Arguments
+///
+/// *  `name`  - The file or folder name.
+/// *  `reserved_bytes`  - Bytes kept free for a suffix appended later, such as an extension.
+/// *  `sanitize`  - The sanitize settings.
+pub fn sanitize_component(name: &str, reserved_bytes: usize, sanitize: &Sanitize) -> String {
+    let name = sanitize_chars(name, sanitize);
+    let max_bytes = sanitize.max_component_bytes.saturating_sub(reserved_bytes);
+    let name = truncate_bytes(name.trim(), max_bytes);
+    // Windows 会去掉结尾的点和空格，导致文件夹无法访问
+    let mut name = name.trim_end_matches(['.', ' ']).to_string();
+
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        name.insert_str(stem.len(), &sanitize.replacement);
+    }
+    if name.is_empty() {
+        name = sanitize.replacement.clone();
+    }
+    name
+}
+
+/// Builds a relative path from a rendered rule, sanitizing every `/` separated component.
+pub fn sanitize_path(path: &str, sanitize: &Sanitize) -> PathBuf {
+    path.split(['/', '\\'])
+        .map(|component| component.trim())
+        .filter(|component| !component.is_empty() && *component != "." && *component != "..")
+        .map(|component| sanitize_component(component, 0, sanitize))
+        .collect()
+}
@@ -3,6 +3,7 @@ use std::ops::Not;
 
 use serde_json::Value;
 
+use crate::actor_db::ActorDb;
 use crate::config::{AppConfig, NumberExtractor, Parser, Translate};
 use crate::parser::Movie;
 use crate::translator::AzureTranslator;
@@ -14,6 +15,7 @@ pub struct Scraping {
     specified_source: Option<String>,
     debug: bool,
     translate: Translate,
+    actor_db: bool,
 }
 
 impl Scraping {
@@ -22,12 +24,14 @@ impl Scraping {
         let sources: Vec<String> = config.get_sources().keys().cloned().collect();
         let parsers = config.get_sources().to_owned();
         let translate = config.translate.clone();
+        let actor_db = config.actor_db.switch;
         Scraping {
             debug,
             sources,
             specified_source: None,
             parsers,
             translate,
+            actor_db,
         }
     }
 
@@ -51,12 +55,31 @@ impl Scraping {
         match movie {
             Some(mut movie) => {
                 movie = self.translate_movie(movie).await;
+                if self.actor_db {
+                    movie = self.normalize_actors(movie);
+                }
                 Some(movie)
             }
             None => None,
         }
     }
 
+    // 用本地演员库把别名统一为标准名
+    fn normalize_actors(&self, mut movie: Movie) -> Movie {
+        let db = ActorDb::get();
+        let actor = db.normalize_actors(&movie.actor);
+        if self.debug && actor != movie.actor {
+            println!("[+]Normalize actors {:?} -> {:?}", movie.actor, actor);
+        }
+        movie.actor = actor;
+        movie.actor_gender = movie
+            .actor_gender
+            .into_iter()
+            .map(|(name, gender)| (db.canonical_name(&name), gender))
+            .collect();
+        movie
+    }
+
     async fn translate_movie(&self, mut movie: Movie) -> Movie {
         if !self.translate.switch {
             return movie;
@@ -4,7 +4,7 @@ use url::Url;
 
 use crate::config::{Rule, StringFlow};
 use crate::request::get_html_content;
-use crate::xpath::{evaluate_xpath_node, value_to_vec_use_handle};
+use crate::xpath::{evaluate_xpath_node_or_empty, value_to_vec_use_handle};
 
 // SiteSearch stores the url to search for IDs and Numbers, options for pre-processing the numbers, the
 // expressions for the numbers and IDs, and options for post-processing the numbers and IDs.
@@ -50,9 +50,9 @@ impl SiteSearch {
     }
 
     fn parse_search_result(&self, document: &Document) -> Vec<(String, String)> {
-        let numbers = evaluate_xpath_node(document.root(), self.expr_number.as_str()).unwrap();
+        let numbers = evaluate_xpath_node_or_empty(document.root(), self.expr_number.as_str());
         let numbers = value_to_vec_use_handle(numbers, &self.site_number_post_handle);
-        let ids = evaluate_xpath_node(document.root(), self.expr_id.as_str()).unwrap();
+        let ids = evaluate_xpath_node_or_empty(document.root(), self.expr_id.as_str());
         let ids = value_to_vec_use_handle(ids, &self.site_id_post_handle);
         let mut number_ids = Vec::new();
         for i in 0..numbers.len() {
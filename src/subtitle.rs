@@ -0,0 +1,110 @@
+use std::fs;
+use std::path::Path;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::AppConfig;
+use crate::probe::MediaInfo;
+
+lazy_static! {
+    static ref CN_SUB_REGEX: Regex = Regex::new(r"[-_]C(\.\w+$|-\w+)|\d+ch(\.\w+$|-\w+)").unwrap();
+}
+
+/// Where the Chinese subtitles of a movie were found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubtitleSource {
+    /// A `-C` suffix or a `subtitle.markers` word in the file path.
+    FileName,
+    /// A subtitle track of the movie.
+    Embedded,
+    /// A subtitle file next to the movie.
+    External,
+}
+
+/// Whether a movie has Chinese subtitles, and how that was found.
+///
+/// The file name is checked first, then the subtitle tracks in `media_info` when
+/// `subtitle.embedded` is on, then with `subtitle.external` the subtitle files next to the movie.
+/// A subtitle file counts only when it has a Chinese language tag such as `movie.chs.srt`, an
+/// untagged `movie.srt` may be in any language.
+pub fn chinese_subtitle(
+    file_path: &str,
+    media_info: Option<&MediaInfo>,
+    config: &AppConfig,
+) -> Option<SubtitleSource> {
+    if CN_SUB_REGEX.is_match(file_path)
+        || config
+            .subtitle
+            .markers
+            .iter()
+            .any(|marker| !marker.is_empty() && file_path.contains(marker.as_str()))
+    {
+        return Some(SubtitleSource::FileName);
+    }
+    if config.subtitle.embedded {
+        if let Some(info) = media_info {
+            if info
+                .subtitles
+                .iter()
+                .any(|language| is_chinese(language, config))
+            {
+                return Some(SubtitleSource::Embedded);
+            }
+        }
+    }
+    if config.subtitle.external && has_external_subtitle(Path::new(file_path), config) {
+        return Some(SubtitleSource::External);
+    }
+    None
+}
+
+/// Whether a language tag such as `chi` or `zh-CN` is one of `subtitle.languages`.
+pub fn is_chinese(language: &str, config: &AppConfig) -> bool {
+    let language = language.to_lowercase();
+    let primary = language.split(['-', '_']).next().unwrap_or_default();
+    config
+        .subtitle
+        .languages
+        .iter()
+        .any(|tag| tag.to_lowercase() == language || tag.to_lowercase() == primary)
+}
+
+fn has_external_subtitle(file_path: &Path, config: &AppConfig) -> bool {
+    let (dir, stem) = match (file_path.parent(), file_path.file_stem()) {
+        (Some(dir), Some(stem)) => (dir, stem.to_string_lossy().to_lowercase()),
+        _ => return false,
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    entries.flatten().any(|entry| {
+        let path = entry.path();
+        let ext = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy().to_lowercase()))
+            .unwrap_or_default();
+        if ext.is_empty()
+            || !config
+                .media
+                .sub_type
+                .split(',')
+                .any(|sub| sub.trim() == ext)
+        {
+            return false;
+        }
+        let sub_stem = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase();
+        // movie.chs.srt, movie.zh-CN.forced.srt
+        match sub_stem.strip_prefix(&stem) {
+            Some(tags) if tags.starts_with('.') => {
+                tags.split('.').any(|tag| is_chinese(tag, config))
+            }
+            _ => false,
+        }
+    })
+}
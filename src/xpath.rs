@@ -1,16 +1,54 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
 use sxd_xpath::nodeset::Node;
-use sxd_xpath::{Context, Error, Factory, Value};
+use sxd_xpath::{Context, Error, Factory, Value, XPath};
 
 use crate::config::{Rule, StringFlow};
 
+thread_local! {
+    // sxd_xpath expressions are neither Send nor Sync, so they can not live in the shared
+    // sources; each thread keeps the expressions it has compiled instead. Only the fixed
+    // expressions of the parsers are cached, so the cache does not grow with the movies.
+    static XPATH_CACHE: RefCell<HashMap<String, Rc<XPath>>> = RefCell::new(HashMap::new());
+}
+
+/// Compiles an XPath expression once and returns the cached expression afterwards.
+pub fn compile_xpath(expr: &str) -> Result<Rc<XPath>, Error> {
+    XPATH_CACHE.with(|cache| {
+        if let Some(expression) = cache.borrow().get(expr) {
+            return Ok(expression.clone());
+        }
+        let expression = build_xpath(expr)?;
+        cache
+            .borrow_mut()
+            .insert(expr.to_string(), expression.clone());
+        Ok(expression)
+    })
+}
+
+/// Compiles an XPath expression without caching it, for expressions built per movie.
+pub fn build_xpath(expr: &str) -> Result<Rc<XPath>, Error> {
+    let factory = Factory::new();
+    let expression = factory.build(expr)?;
+    Ok(Rc::new(expression.ok_or(Error::NoXPath)?))
+}
+
 pub fn evaluate_xpath_node<'d>(node: impl Into<Node<'d>>, expr: &str) -> Result<Value<'d>, Error> {
+    evaluate_xpath(node, expr, compile_xpath)
+}
+
+fn evaluate_xpath<'d>(
+    node: impl Into<Node<'d>>,
+    expr: &str,
+    compile: fn(&str) -> Result<Rc<XPath>, Error>,
+) -> Result<Value<'d>, Error> {
     if expr.is_empty() {
         return Ok(Value::from(""));
     }
 
-    let factory = Factory::new();
-    let expression = factory.build(expr)?;
-    let expression = expression.ok_or(Error::NoXPath)?;
+    let expression = compile(expr)?;
 
     let context = Context::new();
 
@@ -19,6 +57,29 @@ pub fn evaluate_xpath_node<'d>(node: impl Into<Node<'d>>, expr: &str) -> Result<
         .map_err(Into::into)
 }
 
+/// Evaluates an XPath expression, treating a failed evaluation as an empty value.
+pub fn evaluate_xpath_node_or_empty<'d>(node: impl Into<Node<'d>>, expr: &str) -> Value<'d> {
+    or_empty(expr, evaluate_xpath(node, expr, compile_xpath))
+}
+
+/// Like `evaluate_xpath_node_or_empty` for an expression built per movie, which is not cached.
+pub fn evaluate_uncached_xpath_node_or_empty<'d>(
+    node: impl Into<Node<'d>>,
+    expr: &str,
+) -> Value<'d> {
+    or_empty(expr, evaluate_xpath(node, expr, build_xpath))
+}
+
+fn or_empty<'d>(expr: &str, result: Result<Value<'d>, Error>) -> Value<'d> {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            eprintln!("[-]XPath '{}' evaluate failed: {:?}", expr, e);
+            Value::from("")
+        }
+    }
+}
+
 pub fn value_to_vec(value: Value) -> Vec<String> {
     match value {
         Value::Nodeset(nodes) => nodes.iter().map(|node| node.string_value()).collect(),